@@ -0,0 +1,242 @@
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt;
+
+/// A square on the grid, `(x, y)` from the top left.
+pub type Position = (u8, u8);
+
+/// An optional grid to fight on. Every square is five feet and diagonal
+/// steps cost the same as straight ones, so distance is the larger of the
+/// horizontal and vertical gaps.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Battlefield {
+    pub width: u8,
+    pub height: u8,
+    #[serde(default)]
+    pub obstacles: HashSet<Position>,
+}
+
+pub fn distance(from: Position, to: Position) -> u8 {
+    from.0.abs_diff(to.0).max(from.1.abs_diff(to.1))
+}
+
+/// Something between a creature and whatever targets it. Half and
+/// three-quarters cover add to armour class and Dexterity saves; total
+/// cover can't be targeted at all.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Cover {
+    Half,
+    ThreeQuarters,
+    Total,
+}
+
+impl Cover {
+    pub fn bonus(self) -> u8 {
+        match self {
+            Cover::Half => 2,
+            Cover::ThreeQuarters => 5,
+            Cover::Total => 0,
+        }
+    }
+}
+
+impl fmt::Display for Cover {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Cover::Half => write!(f, "half"),
+            Cover::ThreeQuarters => write!(f, "three-quarters"),
+            Cover::Total => write!(f, "total"),
+        }
+    }
+}
+
+impl Battlefield {
+    pub fn is_open(&self, square: Position, occupied: &HashSet<Position>) -> bool {
+        square.0 < self.width && square.1 < self.height
+            && !self.obstacles.contains(&square) && !occupied.contains(&square)
+    }
+
+    fn neighbours(&self, square: Position) -> impl Iterator<Item = Position> + '_ {
+        (-1_i16..=1).flat_map(move |dx| (-1_i16..=1).map(move |dy| (dx, dy)))
+            .filter(|step| *step != (0, 0))
+            .map(move |(dx, dy)| (square.0 as i16 + dx, square.1 as i16 + dy))
+            .filter(move |(x, y)| *x >= 0 && *y >= 0 && *x < self.width as i16 && *y < self.height as i16)
+            .map(|(x, y)| (x as u8, y as u8))
+    }
+
+    /// The shortest walk from `from` to the nearest open square meeting
+    /// `goal`, going around obstacles and `occupied` squares. The path leaves
+    /// out `from` and is empty if `from` already meets the goal.
+    pub fn path_to(&self, from: Position, occupied: &HashSet<Position>, goal: impl Fn(Position) -> bool) -> Option<Vec<Position>> {
+        if goal(from) {
+            return Some(Vec::new());
+        }
+        let mut came_from: HashMap<Position, Position> = HashMap::new();
+        let mut frontier = VecDeque::from(vec![from]);
+
+        while let Some(square) = frontier.pop_front() {
+            for next in self.neighbours(square) {
+                if next == from || came_from.contains_key(&next) || !self.is_open(next, occupied) {
+                    continue;
+                }
+                came_from.insert(next, square);
+                if goal(next) {
+                    let mut path = vec![next];
+                    let mut step = next;
+                    while let Some(previous) = came_from.get(&step).filter(|previous| **previous != from) {
+                        path.push(*previous);
+                        step = *previous;
+                    }
+                    path.reverse();
+                    return Some(path);
+                }
+                frontier.push_back(next);
+            }
+        }
+        None
+    }
+
+    /// The cover obstacles give a creature at `target` from `origin`, after
+    /// the Dungeon Master's Guide: from whichever corner of the origin square
+    /// sees best, draw lines to the four corners of the target's square. One
+    /// or two blocked is half cover, three is three-quarters and all four is
+    /// total cover.
+    pub fn cover(&self, origin: Position, target: Position) -> Option<Cover> {
+        if self.obstacles.is_empty() || origin == target {
+            return None;
+        }
+        let corners = |(x, y): Position| [(0.0, 0.0), (1.0, 0.0), (0.0, 1.0), (1.0, 1.0)].map(|(dx, dy)| (x as f32 + dx, y as f32 + dy));
+        let blocked = corners(origin).iter()
+            .map(|from| corners(target).iter().filter(|to| self.blocks(*from, **to)).count())
+            .min()
+            .unwrap_or(0);
+        match blocked {
+            0 => None,
+            1 | 2 => Some(Cover::Half),
+            3 => Some(Cover::ThreeQuarters),
+            _ => Some(Cover::Total),
+        }
+    }
+
+    /// Whether the line from `from` to `to` passes through obstacles, rather
+    /// than along their outside edges or past a corner. Walls of several
+    /// squares block along the seams between them too.
+    fn blocks(&self, from: (f32, f32), to: (f32, f32)) -> bool {
+        const STEP: f32 = 0.05;
+        const NUDGE: f32 = 0.001;
+        let solid = |x: f32, y: f32| x >= 0.0 && y >= 0.0 && self.obstacles.contains(&(x as u8, y as u8));
+        let steps = ((to.0 - from.0).hypot(to.1 - from.1) / STEP).ceil() as u16;
+        (1..steps).any(|step| {
+            let along = step as f32 / steps as f32;
+            let (x, y) = (from.0 + (to.0 - from.0) * along, from.1 + (to.1 - from.1) * along);
+            solid(x - NUDGE, y - NUDGE) && solid(x + NUDGE, y - NUDGE) && solid(x - NUDGE, y + NUDGE) && solid(x + NUDGE, y + NUDGE)
+        })
+    }
+
+    /// The open square nearest `wanted`, for placing creatures that would
+    /// otherwise start on top of each other or off the map.
+    pub fn nearest_open(&self, wanted: Position, occupied: &HashSet<Position>) -> Option<Position> {
+        let wanted = (wanted.0.min(self.width.saturating_sub(1)), wanted.1.min(self.height.saturating_sub(1)));
+        if self.is_open(wanted, occupied) {
+            return Some(wanted);
+        }
+        self.path_to(wanted, &HashSet::new(), |square| self.is_open(square, occupied))
+            .and_then(|path| path.last().copied())
+    }
+}
+
+/// The area an area of effect covers, sized in squares. A sphere is centred
+/// on its target; cones and lines spread from the attacker toward it.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(tag = "kind")]
+pub enum AreaShape {
+    Sphere { radius: u8 },
+    Cone { length: u8 },
+    Line { length: u8 },
+}
+
+impl AreaShape {
+    pub fn covers(&self, origin: Position, aim: Position, square: Position) -> bool {
+        let (length, half_width) = match *self {
+            AreaShape::Sphere { radius } => return distance(aim, square) <= radius,
+            AreaShape::Cone { length } => (length, None),
+            AreaShape::Line { length } => (length, Some(0.5)),
+        };
+        let toward = (aim.0 as f32 - origin.0 as f32, aim.1 as f32 - origin.1 as f32);
+        let offset = (square.0 as f32 - origin.0 as f32, square.1 as f32 - origin.1 as f32);
+        let toward_length = toward.0.hypot(toward.1);
+        if toward_length == 0.0 {
+            return false;
+        }
+        let along = (offset.0 * toward.0 + offset.1 * toward.1) / toward_length;
+        let across = (offset.0 * toward.1 - offset.1 * toward.0).abs() / toward_length;
+        // a cone is as wide as it is far from the origin
+        along > 0.0 && along <= length as f32 && across <= half_width.unwrap_or(along / 2.0)
+    }
+
+    /// How far away the attacker can aim.
+    pub fn reach(&self, range: u8) -> u8 {
+        match *self {
+            AreaShape::Sphere { .. } => range,
+            AreaShape::Cone { length } | AreaShape::Line { length } => length,
+        }
+    }
+
+    /// Creatures caught when fighting without a battlefield, after the
+    /// Dungeon Master's Guide rule of thumb: one per five feet of a sphere's
+    /// radius, per ten feet of a cone and per thirty feet of a line.
+    pub fn targets_without_grid(&self) -> u8 {
+        match *self {
+            AreaShape::Sphere { radius } => radius,
+            AreaShape::Cone { length } => length / 2,
+            AreaShape::Line { length } => length / 6,
+        }.max(1)
+    }
+}
+
+#[test]
+fn path_around_obstacles_test() {
+    let battlefield = Battlefield {
+        width: 5,
+        height: 5,
+        obstacles: vec![(2, 0), (2, 1), (2, 2), (2, 3)].into_iter().collect(),
+    };
+    let path = battlefield.path_to((0, 0), &HashSet::new(), |square| square == (4, 0)).unwrap();
+
+    assert_eq!(path.last(), Some(&(4, 0)));
+    assert!(path.contains(&(2, 4)), "the only way round is the gap at the bottom");
+    assert!(path.iter().all(|square| !battlefield.obstacles.contains(square)));
+    assert_eq!(battlefield.nearest_open((2, 1), &HashSet::new()).map(|square| distance(square, (2, 1))), Some(1));
+    assert!(battlefield.path_to((0, 0), &vec![(1, 0), (1, 1), (0, 1)].into_iter().collect(), |square| square == (4, 4)).is_none());
+}
+
+#[test]
+fn area_shape_test() {
+    let fireball = AreaShape::Sphere { radius: 4 };
+    assert!(fireball.covers((0, 0), (10, 10), (14, 6)));
+    assert!(!fireball.covers((0, 0), (10, 10), (15, 10)));
+
+    let breath = AreaShape::Cone { length: 3 };
+    assert!(breath.covers((5, 5), (6, 5), (8, 6)), "a cone widens with distance");
+    assert!(!breath.covers((5, 5), (6, 5), (6, 7)));
+    assert!(!breath.covers((5, 5), (6, 5), (4, 5)), "nothing behind the origin");
+
+    let lightning = AreaShape::Line { length: 20 };
+    assert!(lightning.covers((0, 0), (3, 0), (19, 0)));
+    assert!(!lightning.covers((0, 0), (3, 0), (5, 1)));
+    assert_eq!(lightning.targets_without_grid(), 3);
+    assert_eq!(AreaShape::Cone { length: 1 }.targets_without_grid(), 1);
+}
+
+#[test]
+fn cover_test() {
+    let battlefield = |obstacles: Vec<Position>| Battlefield { width: 8, height: 8, obstacles: obstacles.into_iter().collect() };
+
+    assert_eq!(battlefield(vec![]).cover((0, 2), (4, 2)), None);
+    assert_eq!(battlefield(vec![(2, 2)]).cover((0, 2), (4, 2)), Some(Cover::Half), "a pillar in the way");
+    assert_eq!(battlefield(vec![(2, 2)]).cover((4, 2), (0, 2)), Some(Cover::Half));
+    assert_eq!(battlefield(vec![(2, 1), (2, 2), (2, 3)]).cover((0, 2), (4, 2)), Some(Cover::Total), "a wall, seams and all");
+    assert_eq!(battlefield(vec![(2, 3)]).cover((0, 2), (4, 4)), Some(Cover::ThreeQuarters), "a pillar on the diagonal");
+    assert_eq!(battlefield(vec![(2, 4)]).cover((0, 2), (4, 2)), None, "off to the side");
+    assert_eq!(battlefield(vec![(1, 1)]).cover((0, 2), (1, 2)), None, "the next square over");
+}
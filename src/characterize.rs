@@ -1,13 +1,524 @@
-use serde::{Deserialize};
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
 use std::fs;
 use std::{fmt};
+use std::path::Path;
+use std::sync::Arc;
+use std::str::FromStr;
 // use std::fs::File;
-use std::io::{Error as IOError};
+use std::io::{Error as IOError, ErrorKind};
 
-pub fn load_players(file: &str) -> Result<Vec<CharacterStruct>,IOError> {
+use crate::battlefield::{AreaShape, Battlefield, Cover, Position};
+use crate::dice_thrower;
+use crate::targeting::Targeting;
+use crate::ruleset::Rules;
+
+/// The characters to fight and, optionally, the grid they fight on.
+#[derive(Debug, Clone, Default)]
+pub struct Encounter {
+    pub characters: Vec<CharacterStruct>,
+    pub battlefield: Option<Arc<Battlefield>>,
+    /// Rounds before a battle is called a timeout.
+    pub max_rounds: Option<u8>,
+    /// The optional flanking rule: advantage in melee with an ally on the
+    /// target's far side.
+    pub flanking: bool,
+    /// An adventuring day: fights the `characters` take on one after another.
+    pub day: Vec<Stage>,
+    pub reinforcements: Vec<Reinforcement>,
+    pub rules: Rules,
+}
+
+/// Creatures that join a battle part way through, once `trigger` fires.
+/// Reinforcements still to come don't keep a finished battle going.
+#[derive(Debug, Clone)]
+pub struct Reinforcement {
+    pub characters: Vec<CharacterStruct>,
+    pub trigger: Trigger,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(tag = "kind")]
+pub enum Trigger {
+    /// At the start of this round.
+    Round { round: u8 },
+    /// Once a leader on the reinforcements' team drops below this
+    /// percentage of its starting hit points.
+    LeaderBelow { percent: u8 },
+}
+
+/// One fight of an adventuring day, against `characters`, and the rest the
+/// party takes after it.
+#[derive(Debug, Clone, Default)]
+pub struct Stage {
+    pub name: String,
+    pub characters: Vec<CharacterStruct>,
+    pub battlefield: Option<Arc<Battlefield>>,
+    pub reinforcements: Vec<Reinforcement>,
+    pub rest: Option<Rest>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rest {
+    Short,
+    Long,
+}
+
+impl Encounter {
+    /// Everyone who can end up fighting: the characters, every stage of the
+    /// day, all reinforcements and whatever any of them can summon.
+    pub fn every_character(&self) -> Vec<&CharacterStruct> {
+        let mut characters: Vec<&CharacterStruct> = self.characters.iter()
+            .chain(self.reinforcements.iter().flat_map(|reinforcement| &reinforcement.characters))
+            .chain(self.day.iter().flat_map(|stage| stage.characters.iter()
+                .chain(stage.reinforcements.iter().flat_map(|reinforcement| &reinforcement.characters))))
+            .collect();
+        let mut summoners = 0;
+        while summoners < characters.len() {
+            let summoned = characters[summoners].summons.iter().flat_map(|summon| &summon.creatures);
+            characters.extend(summoned);
+            summoners += 1;
+        }
+        characters
+    }
+}
+
+/// Loads a roster; a `level` puts every character that has a class at that
+/// level, overriding whatever level the file gives them.
+pub fn load_encounter(file: &str, level: Option<u8>) -> Result<Encounter,IOError> {
     let contents = read_file(file)?;
-    let deserial: Vec<CharacterStruct> = serde_json::from_str(&contents)?;
-    Ok(deserial)
+    let format = InputFormat::from_file_name(file)?;
+    let mut roster = Roster::parse(format, &contents)?;
+    if level.is_some() {
+        roster.level_override = level;
+    }
+    let mut day = Vec::with_capacity(roster.encounters.len());
+    for (number, stage) in roster.encounters.iter().enumerate() {
+        day.push(Stage {
+            name: stage.name.clone().unwrap_or_else(|| format!("Encounter {}", number + 1)),
+            characters: roster.spawn(&stage.characters)?,
+            battlefield: stage.battlefield.clone().map(Arc::new),
+            reinforcements: roster.reinforce(&stage.reinforcements)?,
+            rest: stage.rest,
+        });
+    }
+    Ok(Encounter {
+        characters: roster.spawn_characters()?,
+        reinforcements: roster.reinforce(&roster.reinforcements)?,
+        battlefield: roster.battlefield.map(Arc::new),
+        max_rounds: roster.max_rounds,
+        flanking: roster.flanking,
+        rules: roster.ruleset,
+        day,
+    })
+}
+
+#[cfg(test)]
+pub fn load_players(file: &str, level: Option<u8>) -> Result<Vec<CharacterStruct>,IOError> {
+    load_encounter(file, level).map(|encounter| encounter.characters)
+}
+
+/// A roster is either a bare list of characters or a table of reusable
+/// `templates`, class `classes` and the `characters` spawned from them. TOML
+/// has no top level arrays, so its characters always live under `[[characters]]`.
+#[derive(Deserialize, Default)]
+struct Roster {
+    #[serde(default)]
+    templates: HashMap<String, CharacterTemplate>,
+    #[serde(default)]
+    classes: HashMap<String, ClassDefinition>,
+    /// Level for classed characters that don't give their own.
+    #[serde(default)]
+    level: Option<u8>,
+    #[serde(skip)]
+    level_override: Option<u8>,
+    #[serde(default)]
+    battlefield: Option<Battlefield>,
+    /// Rounds before a battle is called a timeout; `--max-rounds` overrides it.
+    #[serde(default)]
+    max_rounds: Option<u8>,
+    /// The game system to fight under; `--ruleset` overrides it.
+    #[serde(default)]
+    ruleset: Rules,
+    /// Use the optional flanking rule; `--flanking` turns it on too.
+    #[serde(default)]
+    flanking: bool,
+    characters: Vec<CharacterTemplate>,
+    /// With encounters, `characters` is the party and fights each in turn.
+    #[serde(default)]
+    encounters: Vec<RosterEncounter>,
+    #[serde(default)]
+    reinforcements: Vec<RosterReinforcement>,
+}
+
+#[derive(Deserialize)]
+struct RosterReinforcement {
+    characters: Vec<CharacterTemplate>,
+    trigger: Trigger,
+}
+
+#[derive(Deserialize)]
+struct RosterEncounter {
+    #[serde(default)]
+    name: Option<String>,
+    characters: Vec<CharacterTemplate>,
+    #[serde(default)]
+    battlefield: Option<Battlefield>,
+    #[serde(default)]
+    reinforcements: Vec<RosterReinforcement>,
+    /// The rest taken after this encounter.
+    #[serde(default)]
+    rest: Option<Rest>,
+}
+
+impl Roster {
+    fn parse(format: InputFormat, contents: &str) -> Result<Self,IOError> {
+        if format == InputFormat::Toml {
+            return format.parse(contents);
+        }
+        match format.parse::<Vec<CharacterTemplate>>(contents) {
+            Ok(characters) => Ok(Roster { characters, ..Default::default() }),
+            Err(list_error) => format.parse(contents).map_err(|table_error|
+                IOError::new(ErrorKind::InvalidData, format!(
+                    "Neither a character list ({}) nor a roster table ({})", list_error, table_error))),
+        }
+    }
+
+    fn spawn_characters(&self) -> Result<Vec<CharacterStruct>,IOError> {
+        self.spawn(&self.characters)
+    }
+
+    fn reinforce(&self, reinforcements: &[RosterReinforcement]) -> Result<Vec<Reinforcement>,IOError> {
+        reinforcements.iter()
+            .map(|reinforcement| Ok(Reinforcement {
+                characters: self.spawn(&reinforcement.characters)?,
+                trigger: reinforcement.trigger,
+            }))
+            .collect()
+    }
+
+    fn spawn(&self, entries: &[CharacterTemplate]) -> Result<Vec<CharacterStruct>,IOError> {
+        self.spawn_with(entries, true)
+    }
+
+    /// Summoned creatures are spawned without summons of their own, so a
+    /// template can't summon itself forever.
+    fn spawn_with(&self, entries: &[CharacterTemplate], summons: bool) -> Result<Vec<CharacterStruct>,IOError> {
+        let mut characters = Vec::with_capacity(entries.len());
+
+        for entry in entries {
+            let count = entry.count.unwrap_or(1);
+            let mut resolved = self.resolve(entry, &mut Vec::new())?;
+            if resolved.name.is_none() {
+                resolved.name = entry.template.clone();
+            }
+            resolved.level = self.level_override.or(resolved.level).or(self.level);
+            let class = match &resolved.class {
+                Some(class_name) => Some(self.classes.get(class_name).ok_or_else(||
+                    IOError::new(ErrorKind::InvalidData, format!("Unknown class: {}", class_name)))?),
+                None => None,
+            };
+            let summon_templates = if summons { resolved.summons.clone().unwrap_or_default() } else { Vec::new() };
+            let mut character = resolved.into_character(class)?.apply_hit_points();
+            for summon in summon_templates {
+                let called = CharacterTemplate { template: Some(summon.template), count: summon.count, ..Default::default() };
+                let mut creatures = self.spawn_with(&[called], false)?;
+                for creature in creatures.iter_mut() {
+                    creature.team = character.team;
+                }
+                character.summons.push(Summon { name: summon.name, creatures, uses: summon.uses });
+            }
+
+            if count == 1 {
+                characters.push(character);
+                continue;
+            }
+            for number in 1..=count {
+                let mut spawn = character.clone();
+                spawn.name = format!("{} {}", character.name, number);
+                characters.push(spawn);
+            }
+        }
+        Ok(characters)
+    }
+
+    /// Layers `entry` over the chain of templates it inherits from.
+    fn resolve(&self, entry: &CharacterTemplate, chain: &mut Vec<String>) -> Result<CharacterTemplate,IOError> {
+        let template_name = match &entry.template {
+            Some(template_name) => template_name,
+            None => return Ok(entry.clone()),
+        };
+        if chain.contains(template_name) {
+            return Err(IOError::new(ErrorKind::InvalidData, format!("Template cycle: {} -> {}", chain.join(" -> "), template_name)));
+        }
+        chain.push(template_name.clone());
+
+        let template = self.templates.get(template_name).ok_or_else(||
+            IOError::new(ErrorKind::InvalidData, format!("Unknown template: {}", template_name)))?;
+        let base = self.resolve(template, chain)?;
+        Ok(entry.clone().over(base))
+    }
+}
+
+/// A possibly partial character: a template, or an entry in a roster that
+/// overrides the template it names and may spawn `count` numbered copies.
+#[derive(Deserialize, Default, Debug, Clone)]
+pub struct CharacterTemplate {
+    pub template: Option<String>,
+    pub count: Option<u16>,
+    pub name: Option<String>,
+    pub armour_class: Option<u8>,
+    pub to_hit: Option<u8>,
+    pub weapon: Option<String>,
+    pub actions_per_round: Option<u8>,
+    pub damage: Option<u8>,
+    pub team: Option<Team>,
+    pub hs2: Option<HealthState>,
+    pub hp: Option<HitPoints>,
+    pub hp_mode: Option<HitPointMode>,
+    pub class: Option<String>,
+    pub level: Option<u8>,
+    pub constitution: Option<u8>,
+    pub dexterity: Option<u8>,
+    pub armour: Option<Armour>,
+    pub shield: Option<u8>,
+    pub effects: Option<Vec<Effect>>,
+    pub targeting: Option<Targeting>,
+    pub policy: Option<String>,
+    /// Features beyond any a class gives, such as a monster's Pack Tactics.
+    pub features: Option<Vec<String>>,
+    pub crit_range: Option<u8>,
+    pub passive_perception: Option<u8>,
+    pub position: Option<Position>,
+    pub speed: Option<u8>,
+    pub reach: Option<u8>,
+    pub range: Option<WeaponRange>,
+    pub area_attacks: Option<Vec<AreaAttack>>,
+    pub morale: Option<Morale>,
+    pub leader: Option<bool>,
+    pub summons: Option<Vec<SummonTemplate>>,
+    pub heals: Option<Vec<Heal>>,
+    pub heal_priority: Option<HealPriority>,
+    pub cover: Option<Cover>,
+}
+
+impl CharacterTemplate {
+    /// Fields set here win; anything unset falls through to `base`. A count
+    /// belongs to the entry doing the spawning and is never inherited.
+    fn over(self, base: CharacterTemplate) -> Self {
+        CharacterTemplate {
+            template: base.template,
+            count: self.count,
+            name: self.name.or(base.name),
+            armour_class: self.armour_class.or(base.armour_class),
+            to_hit: self.to_hit.or(base.to_hit),
+            weapon: self.weapon.or(base.weapon),
+            actions_per_round: self.actions_per_round.or(base.actions_per_round),
+            damage: self.damage.or(base.damage),
+            team: self.team.or(base.team),
+            hs2: self.hs2.or(base.hs2),
+            hp: self.hp.or(base.hp),
+            hp_mode: self.hp_mode.or(base.hp_mode),
+            class: self.class.or(base.class),
+            level: self.level.or(base.level),
+            constitution: self.constitution.or(base.constitution),
+            dexterity: self.dexterity.or(base.dexterity),
+            armour: self.armour.or(base.armour),
+            shield: self.shield.or(base.shield),
+            effects: self.effects.or(base.effects),
+            targeting: self.targeting.or(base.targeting),
+            policy: self.policy.or(base.policy),
+            features: self.features.or(base.features),
+            crit_range: self.crit_range.or(base.crit_range),
+            passive_perception: self.passive_perception.or(base.passive_perception),
+            position: self.position.or(base.position),
+            speed: self.speed.or(base.speed),
+            reach: self.reach.or(base.reach),
+            range: self.range.or(base.range),
+            area_attacks: self.area_attacks.or(base.area_attacks),
+            morale: self.morale.or(base.morale),
+            leader: self.leader.or(base.leader),
+            summons: self.summons.or(base.summons),
+            heals: self.heals.or(base.heals),
+            heal_priority: self.heal_priority.or(base.heal_priority),
+            cover: self.cover.or(base.cover),
+        }
+    }
+
+    /// A class derives hit points, attacks per round, proficiency and
+    /// features from the level, ignoring any `hp` or `actions_per_round` given.
+    fn into_character(self, class: Option<&ClassDefinition>) -> Result<CharacterStruct,IOError> {
+        let name = self.name.ok_or_else(|| missing_field("<unnamed>", "name"))?;
+        let missing = |field| missing_field(&name, field);
+
+        if let Some(heal) = self.heals.iter().flatten().find(|heal| heal.healing.is_none() && heal.pool.is_none()) {
+            return Err(IOError::new(ErrorKind::InvalidData, format!("{}'s {} needs healing dice or a pool", name, heal.name)));
+        }
+        if let Some(area) = self.area_attacks.iter().flatten().find(|area| matches!(area.shape, AreaShape::Sphere { .. }) && area.range == 0) {
+            return Err(IOError::new(ErrorKind::InvalidData, format!("{}'s {} is a sphere and needs a range", name, area.name)));
+        }
+        if let Some(HitPoints::Dice(hit_dice)) = &self.hp {
+            if dice_thrower::parse_request(hit_dice).is_none() {
+                return Err(IOError::new(ErrorKind::InvalidData, format!("{} has unreadable hit dice: {}", name, hit_dice)));
+            }
+        }
+
+        let base_armour_class = match self.armour {
+            Some(_) => self.armour_class.unwrap_or(10),
+            None => self.armour_class.ok_or_else(|| missing("armour_class"))?,
+        };
+
+        let mut character = CharacterStruct {
+            base_armour_class,
+            armour: self.armour,
+            shield: self.shield.unwrap_or(0),
+            dexterity_modifier: ability_modifier(self.dexterity.unwrap_or(10)),
+            constitution_modifier: ability_modifier(self.constitution.unwrap_or(10)),
+            effects: self.effects.unwrap_or_default(),
+            targeting: self.targeting.unwrap_or_default(),
+            policy: self.policy,
+            features: self.features.unwrap_or_default(),
+            crit_range: self.crit_range,
+            passive_perception: self.passive_perception,
+            position: self.position,
+            speed: self.speed,
+            reach: self.reach,
+            range: self.range,
+            area_attacks: self.area_attacks.unwrap_or_default(),
+            heals: self.heals.unwrap_or_default(),
+            heal_priority: self.heal_priority.unwrap_or_default(),
+            cover: self.cover,
+            morale: self.morale,
+            leader: self.leader.unwrap_or(false),
+            to_hit: self.to_hit.ok_or_else(|| missing("to_hit"))?,
+            weapon: self.weapon.ok_or_else(|| missing("weapon"))?,
+            damage: self.damage.ok_or_else(|| missing("damage"))?,
+            team: self.team.ok_or_else(|| missing("team"))?,
+            hs2: self.hs2.unwrap_or_default(),
+            hp_mode: self.hp_mode.unwrap_or_default(),
+            class: self.class,
+            name: name.clone(),
+            ..Default::default()
+        };
+
+        match class {
+            Some(class) => {
+                let level = self.level.unwrap_or(1);
+                character.hp = Some(HitPoints::Fixed(class.hit_points(level, character.constitution_modifier)));
+                character.actions_per_round = class.attacks_per_round(level);
+                character.proficiency_bonus = proficiency_bonus(level);
+                character.features.extend(class.features(level));
+                character.level = level;
+                character.hit_die = Some(class.hit_die);
+                character.hit_dice = level;
+            }
+            None => {
+                character.hp = self.hp;
+                character.actions_per_round = self.actions_per_round.ok_or_else(|| missing("actions_per_round"))?;
+            }
+        }
+        character.update_armour_class(1);
+        Ok(character)
+    }
+}
+
+/// How a class grows: its hit die and, per level reached, any change to
+/// attacks per round and the features gained.
+#[derive(Deserialize, Debug, Clone)]
+pub struct ClassDefinition {
+    pub hit_die: u8,
+    #[serde(default)]
+    pub levels: Vec<ClassLevel>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ClassLevel {
+    pub level: u8,
+    pub attacks_per_round: Option<u8>,
+    #[serde(default)]
+    pub features: Vec<String>,
+}
+
+impl ClassDefinition {
+    /// Maximum hit die at first level, the rounded up average for every level after.
+    pub fn hit_points(&self, level: u8, constitution_modifier: i8) -> u16 {
+        let first_level = (self.hit_die as i16 + constitution_modifier as i16).max(1);
+        let later_level = (self.hit_die as i16 / 2 + 1 + constitution_modifier as i16).max(1);
+        (first_level + later_level * (level.max(1) as i16 - 1)) as u16
+    }
+
+    pub fn attacks_per_round(&self, level: u8) -> u8 {
+        self.reached(level)
+            .filter_map(|class_level| class_level.attacks_per_round)
+            .last()
+            .unwrap_or(1)
+    }
+
+    pub fn features(&self, level: u8) -> Vec<String> {
+        self.reached(level)
+            .flat_map(|class_level| class_level.features.iter().cloned())
+            .collect()
+    }
+
+    fn reached(&self, level: u8) -> impl Iterator<Item = &ClassLevel> {
+        let mut levels: Vec<&ClassLevel> = self.levels.iter()
+            .filter(|class_level| class_level.level <= level)
+            .collect();
+        levels.sort_by_key(|class_level| class_level.level);
+        levels.into_iter()
+    }
+}
+
+pub fn proficiency_bonus(level: u8) -> u8 {
+    2 + (level.max(1) - 1) / 4
+}
+
+pub fn ability_modifier(score: u8) -> i8 {
+    (score as i8 - 10).div_euclid(2)
+}
+
+fn missing_field(name: &str, field: &str) -> IOError {
+    IOError::new(ErrorKind::InvalidData, format!("{} is missing {}", name, field))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InputFormat {
+    Json,
+    Yaml,
+    Toml,
+    Ron,
+}
+
+impl InputFormat {
+    pub fn from_file_name(file: &str) -> Result<Self,IOError> {
+        let extension = Path::new(file).extension()
+            .and_then(|extension| extension.to_str())
+            .map(|extension| extension.to_ascii_lowercase());
+
+        match extension.as_deref() {
+            Some("json") => Ok(InputFormat::Json),
+            Some("yaml") | Some("yml") => Ok(InputFormat::Yaml),
+            Some("toml") => Ok(InputFormat::Toml),
+            Some("ron") => Ok(InputFormat::Ron),
+            _ => Err(IOError::new(ErrorKind::InvalidInput, format!("Unsupported input format: {}", file))),
+        }
+    }
+
+    pub fn parse<T: DeserializeOwned>(&self, contents: &str) -> Result<T,IOError> {
+        match self {
+            InputFormat::Json => serde_json::from_str(contents).map_err(invalid_data),
+            InputFormat::Yaml => serde_yaml::from_str(contents).map_err(invalid_data),
+            InputFormat::Toml => toml::from_str(contents).map_err(invalid_data),
+            InputFormat::Ron => ron::Options::default()
+                .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+                .from_str(contents).map_err(invalid_data),
+        }
+    }
+}
+
+fn invalid_data<E: std::error::Error + Send + Sync + 'static>(error: E) -> IOError {
+    IOError::new(ErrorKind::InvalidData, error)
 }
 
 fn read_file(file: &str) -> Result<String,std::io::Error>{
@@ -15,38 +526,45 @@ fn read_file(file: &str) -> Result<String,std::io::Error>{
     Ok(contents)
 }
 
+#[cfg(test)]
 pub fn get_players() -> Vec<CharacterStruct> {
     let player1 = CharacterStruct {
         name: String::from("Hero"),
         armour_class: 12,
+        base_armour_class: 12,
         to_hit: 20,
         weapon: "1d6".to_string(),
         actions_per_round: 1,
         damage: 6,  
         team: Team::Heros,
         hs2: HealthState::Alive(10),
+        ..Default::default()
     };
 
     let player2 = CharacterStruct {
         name: String::from("Villan-A"),
         armour_class: 10,
+        base_armour_class: 10,
         to_hit: 20,
         weapon: "1d4".to_string(),
         actions_per_round: 1,
         damage: 4,  
         team: Team::Villains,
         hs2: HealthState::Alive(6),
+        ..Default::default()
     };
 
     let player3 = CharacterStruct {
         name: String::from("Villan-B"),
         armour_class: 10,
+        base_armour_class: 10,
         to_hit: 20,
         weapon: "1d4".to_string(),
         actions_per_round: 1,
         damage: 4,  
         team: Team::Villains,
         hs2: HealthState::Alive(6),
+        ..Default::default()
     };
 
     // let player4 = CharacterStruct {
@@ -74,15 +592,425 @@ pub struct CharacterStruct {
     pub actions_per_round: u8,
     pub damage: u8,
     pub team: Team,
+    #[serde(default)]
     pub hs2: HealthState,
+    #[serde(default)]
+    pub hp: Option<HitPoints>,
+    #[serde(default)]
+    pub hp_mode: HitPointMode,
+    #[serde(default)]
+    pub class: Option<String>,
+    #[serde(default)]
+    pub level: u8,
+    #[serde(default)]
+    pub proficiency_bonus: u8,
+    #[serde(default)]
+    pub features: Vec<String>,
+    /// Armour class when no armour is worn, before shield and effects.
+    #[serde(default)]
+    pub base_armour_class: u8,
+    #[serde(default)]
+    pub armour: Option<Armour>,
+    #[serde(default)]
+    pub shield: u8,
+    #[serde(default)]
+    pub dexterity_modifier: i8,
+    #[serde(default)]
+    pub constitution_modifier: i8,
+    #[serde(default)]
+    pub effects: Vec<Effect>,
+    #[serde(default)]
+    pub targeting: Targeting,
+    /// Name of the combat policy choosing this character's actions.
+    #[serde(default)]
+    pub policy: Option<String>,
+    /// Lowest natural roll that scores a critical hit, overriding any from features.
+    #[serde(default)]
+    pub crit_range: Option<u8>,
+    /// What a Hide has to beat, 10 if unset.
+    #[serde(default)]
+    pub passive_perception: Option<u8>,
+    /// Starting square when fighting on a battlefield.
+    #[serde(default)]
+    pub position: Option<Position>,
+    /// Squares moved per turn, 6 (30 feet) if unset.
+    #[serde(default)]
+    pub speed: Option<u8>,
+    /// Squares a melee attack reaches, 1 if unset.
+    #[serde(default)]
+    pub reach: Option<u8>,
+    /// Set for ranged weapons, replacing reach.
+    #[serde(default)]
+    pub range: Option<WeaponRange>,
+    #[serde(default)]
+    pub area_attacks: Vec<AreaAttack>,
+    /// When this character gives up the fight; unset fights to the end.
+    #[serde(default)]
+    pub morale: Option<Morale>,
+    /// Whose fall can break the morale of the rest of the team.
+    #[serde(default)]
+    pub leader: bool,
+    /// The class hit die, spent to heal on a short rest.
+    #[serde(default)]
+    pub hit_die: Option<u8>,
+    /// Hit dice left to spend.
+    #[serde(default)]
+    pub hit_dice: u8,
+    #[serde(default)]
+    pub summons: Vec<Summon>,
+    #[serde(default)]
+    pub heals: Vec<Heal>,
+    #[serde(default)]
+    pub heal_priority: HealPriority,
+    /// Cover it fights from against every attacker, such as an arrow slit,
+    /// on top of whatever the battlefield's obstacles give it.
+    #[serde(default)]
+    pub cover: Option<Cover>,
 }
 
-#[derive(Deserialize, Clone, PartialEq, Eq, PartialOrd, Ord, Copy, Debug)]
+impl CharacterStruct {
+    pub fn passive_perception(&self) -> u8 {
+        self.passive_perception.unwrap_or(10)
+    }
+
+    pub fn hit_points(&self) -> u16 {
+        match self.hs2 {
+            HealthState::Alive(hit_points) => hit_points,
+            HealthState::Ko | HealthState::Dead => 0,
+        }
+    }
+
+    /// Spends hit dice until back to the hit points of `rested`, the
+    /// character fresh at the start of the day, and regains area attacks
+    /// that recharge on a short rest. Someone knocked out stays down.
+    pub fn short_rest(&mut self, rested: &CharacterStruct) {
+        if let (HealthState::Alive(mut hit_points), Some(hit_die)) = (self.hs2, self.hit_die) {
+            let roll_request = dice_thrower::parse_request(&format!("1d{}", hit_die)).unwrap();
+            while self.hit_dice > 0 && hit_points < rested.hit_points() {
+                self.hit_dice -= 1;
+                let healed = (dice_thrower::throw_roll(&roll_request) as i16 + self.constitution_modifier as i16).max(0) as u16;
+                hit_points = (hit_points + healed).min(rested.hit_points());
+            }
+            self.hs2 = HealthState::Alive(hit_points);
+        }
+        self.recharge(rested, Rest::Short);
+    }
+
+    /// Back to full hit points with every area attack and half the hit dice
+    /// regained, unless dead.
+    pub fn long_rest(&mut self, rested: &CharacterStruct) {
+        if self.hs2 == HealthState::Dead {
+            return;
+        }
+        self.hs2 = rested.hs2;
+        self.hit_dice = rested.hit_dice.min(self.hit_dice + (rested.hit_dice / 2).max(1));
+        self.recharge(rested, Rest::Long);
+    }
+
+    fn recharge(&mut self, rested: &CharacterStruct, rest: Rest) {
+        for (area, fresh) in self.area_attacks.iter_mut().zip(&rested.area_attacks) {
+            if rest == Rest::Long || area.recharge == Some(Rest::Short) {
+                area.uses = fresh.uses;
+            }
+        }
+        for (heal, fresh) in self.heals.iter_mut().zip(&rested.heals) {
+            if rest == Rest::Long || heal.recharge == Some(Rest::Short) {
+                heal.uses = fresh.uses;
+                heal.pool = fresh.pool;
+            }
+        }
+    }
+
+    pub fn speed(&self) -> u8 {
+        self.speed.unwrap_or(6)
+    }
+
+    /// The furthest this character can attack, in squares.
+    pub fn attack_range(&self) -> u8 {
+        match self.range {
+            Some(range) => range.long,
+            None => self.reach.unwrap_or(1),
+        }
+    }
+
+    pub fn saving_throw(&self, ability: SaveAbility) -> i8 {
+        match ability {
+            SaveAbility::Dexterity => self.dexterity_modifier,
+            SaveAbility::Constitution => self.constitution_modifier,
+        }
+    }
+
+    /// The first summons with uses left this battle.
+    pub fn usable_summon(&self) -> Option<usize> {
+        self.summons.iter().position(|summon| summon.uses != Some(0))
+    }
+
+    /// The first heal with uses, or a pool, left this battle that can be used
+    /// on the character itself, or with `on_self` false on someone else.
+    pub fn usable_heal(&self, on_self: bool) -> Option<usize> {
+        self.heals.iter().position(|heal| heal.uses != Some(0) && heal.pool != Some(0) && (on_self || !heal.self_only))
+    }
+
+    /// The first area attack with uses left this battle.
+    pub fn usable_area_attack(&self) -> Option<usize> {
+        self.area_attacks.iter().position(|area| area.uses != Some(0))
+    }
+
+    /// How close this character likes to be: within reach, or normal range.
+    pub fn preferred_range(&self) -> u8 {
+        match self.range {
+            Some(range) => range.normal,
+            None => self.reach.unwrap_or(1),
+        }
+    }
+
+    /// Whether an effect active in `round` outlines this character.
+    pub fn outlined(&self, round: u8) -> bool {
+        self.effects.iter().any(|effect| effect.outlined && effect.is_active(round))
+    }
+
+    /// Recomputes armour class from armour, shield and the effects active in
+    /// `round`, returning true if it changed.
+    pub fn update_armour_class(&mut self, round: u8) -> bool {
+        let worn = match &self.armour {
+            Some(armour) => armour.armour_class(self.dexterity_modifier),
+            None => self.base_armour_class as i16,
+        };
+        let effects: i16 = self.effects.iter()
+            .filter(|effect| effect.is_active(round))
+            .map(|effect| effect.armour_class_bonus as i16)
+            .sum();
+        let armour_class = (worn + self.shield as i16 + effects).clamp(0, u8::MAX as i16) as u8;
+
+        let changed = armour_class != self.armour_class;
+        self.armour_class = armour_class;
+        changed
+    }
+
+    /// `hp: 10` is shorthand for `hs2: {"Alive": 10}` and wins if both are given.
+    /// Hit dice such as `hp: "7d8+14"` start at their average until a battle rolls them,
+    /// and never below 1 however large a `-N` modifier is.
+    pub fn apply_hit_points(mut self) -> Self {
+        match &self.hp {
+            Some(HitPoints::Fixed(hit_points)) => self.hs2 = HealthState::Alive(*hit_points),
+            Some(HitPoints::Dice(hit_dice)) => {
+                if let Some(roll_request) = dice_thrower::parse_request(hit_dice) {
+                    self.hs2 = HealthState::Alive(dice_thrower::average_roll(&roll_request).max(1));
+                }
+            }
+            None => (),
+        }
+        self
+    }
+}
+
+/// Starting hit points, either a flat number or a stat block dice expression.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(untagged)]
+pub enum HitPoints {
+    Fixed(u16),
+    Dice(String),
+}
+
+/// Whether hit dice are rolled afresh for every battle or fixed at their average.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum HitPointMode {
+    #[default]
+    Roll,
+    Average,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ArmourKind {
+    Light,
+    Medium,
+    Heavy,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Armour {
+    pub kind: ArmourKind,
+    pub base: u8,
+    #[serde(default)]
+    pub magic_bonus: u8,
+}
+
+impl Armour {
+    /// Light armour takes the full DEX modifier, medium at most +2 and heavy none.
+    pub fn armour_class(&self, dexterity_modifier: i8) -> i16 {
+        let dexterity_bonus = match self.kind {
+            ArmourKind::Light => dexterity_modifier,
+            ArmourKind::Medium => dexterity_modifier.min(2),
+            ArmourKind::Heavy => 0,
+        };
+        self.base as i16 + self.magic_bonus as i16 + dexterity_bonus as i16
+    }
+}
+
+/// Calls `count` creatures made from `template` onto the summoner's side,
+/// up to `uses` times a battle, or without limit if unset.
+#[derive(Deserialize, Debug, Clone)]
+pub struct SummonTemplate {
+    pub name: String,
+    pub template: String,
+    #[serde(default)]
+    pub count: Option<u16>,
+    #[serde(default)]
+    pub uses: Option<u8>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Summon {
+    pub name: String,
+    pub creatures: Vec<CharacterStruct>,
+    pub uses: Option<u8>,
+}
+
+/// Morale breaks when the character drops below `below_percent` of its
+/// starting hit points, its team below `team_below_percent` of theirs, or,
+/// with `leader_loss`, once every leader on its team is out of the fight.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Morale {
+    #[serde(default)]
+    pub below_percent: Option<u8>,
+    #[serde(default)]
+    pub team_below_percent: Option<u8>,
+    #[serde(default)]
+    pub leader_loss: bool,
+    #[serde(default)]
+    pub response: MoraleResponse,
+}
+
+/// A way to restore hit points: a spell such as Cure Wounds, a potion,
+/// Second Wind or a Lay on Hands pool. `healing` dice are rolled each time,
+/// while a `pool` is spent a point per hit point restored until it runs
+/// dry. `uses` and `recharge` work as for area attacks.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Heal {
+    pub name: String,
+    #[serde(default)]
+    pub healing: Option<String>,
+    #[serde(default)]
+    pub pool: Option<u16>,
+    /// Squares away the patient can be, 1 (touch) if unset.
+    #[serde(default = "touch")]
+    pub range: u8,
+    /// Only ever heals the character using it, like Second Wind.
+    #[serde(default)]
+    pub self_only: bool,
+    #[serde(default)]
+    pub uses: Option<u8>,
+    #[serde(default)]
+    pub recharge: Option<Rest>,
+}
+
+fn touch() -> u8 { 1 }
+
+/// When a character with heals spends its turn on them rather than fighting.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(tag = "when")]
+pub enum HealPriority {
+    /// Only to get allies who are down back on their feet.
+    #[default]
+    DownedFirst,
+    /// Allies who are down first, then whoever is furthest below `percent`
+    /// of their starting hit points, the healer included.
+    Below { percent: u8 },
+    Never,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum MoraleResponse {
+    #[default]
+    Flee,
+    Surrender,
+}
+
+/// Normal and long range of a ranged weapon, in squares; attacks beyond
+/// normal range are made at disadvantage.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct WeaponRange {
+    pub normal: u8,
+    pub long: u8,
+}
+
+/// A fireball, breath weapon or cleave: damage rolled once for everyone in
+/// the `shape`, each of whom saves against `save_dc` for half, or for none
+/// unless `half_on_save`. `uses` limits it per battle, or per adventuring day
+/// until the `recharge` rest comes round, a long rest if unset; unset `uses`
+/// is unlimited.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct AreaAttack {
+    pub name: String,
+    pub damage: String,
+    pub shape: AreaShape,
+    /// Squares away a sphere can be centred; spheres must give one.
+    #[serde(default)]
+    pub range: u8,
+    pub save: SaveAbility,
+    pub save_dc: u8,
+    #[serde(default = "half_on_save")]
+    pub half_on_save: bool,
+    #[serde(default)]
+    pub uses: Option<u8>,
+    #[serde(default)]
+    pub recharge: Option<Rest>,
+}
+
+fn half_on_save() -> bool { true }
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SaveAbility {
+    Dexterity,
+    Constitution,
+}
+
+/// A temporary modifier such as Shield of Faith or cover, active from
+/// `start_round` for `duration` rounds, or for the rest of the battle.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Effect {
+    pub name: String,
+    #[serde(default)]
+    pub armour_class_bonus: i8,
+    #[serde(default = "first_round")]
+    pub start_round: u8,
+    #[serde(default)]
+    pub duration: Option<u8>,
+    /// Outlined in light, as by Faerie Fire, giving attacks against advantage.
+    #[serde(default)]
+    pub outlined: bool,
+}
+
+fn first_round() -> u8 { 1 }
+
+impl Effect {
+    pub fn is_active(&self, round: u8) -> bool {
+        round >= self.start_round && match self.duration {
+            Some(duration) => (round as u16) < self.start_round as u16 + duration as u16,
+            None => true,
+        }
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone, PartialEq, Eq, PartialOrd, Ord, Copy, Debug, Default)]
 pub enum Team {
+    #[default]
     Heros,
     Villains,
 }
 
+impl FromStr for Team {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "Heros" => Ok(Team::Heros),
+            "Villains" => Ok(Team::Villains),
+            _ => Err(format!("Unknown team: {}", name)),
+        }
+    }
+}
+
 impl fmt::Display for Team {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
@@ -92,39 +1020,40 @@ impl fmt::Display for Team {
     }
 }
 
-impl Default for Team {
-    fn default() -> Self { Team::Heros }
-}
-
-#[derive(Deserialize, Debug, Clone, Copy, PartialOrd, Eq, Ord, PartialEq)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialOrd, Eq, Ord, PartialEq, Default)]
 pub enum HealthState {
+    #[default]
     Dead,
     Ko,
     Alive(u16),
 }
 
-impl Default for HealthState {
-    fn default() -> Self {
-        HealthState::Dead        
-    }
-}
+#[test]
+fn roster_template_errors_test() {
+    let cycle = r#"{"templates": {"a": {"template": "b"}, "b": {"template": "a"}}, "characters": [{"template": "a"}]}"#;
+    let roster = Roster::parse(InputFormat::Json, cycle).unwrap();
+    assert!(roster.spawn_characters().unwrap_err().to_string().contains("cycle"));
 
-impl HealthState {
-    pub fn update_health_state(self, modifier: i16) -> Self {
-        const KO_THRESHOLD: i16 = -10;
+    let unknown = r#"{"characters": [{"template": "nobody"}]}"#;
+    let roster = Roster::parse(InputFormat::Json, unknown).unwrap();
+    assert!(roster.spawn_characters().unwrap_err().to_string().contains("Unknown template"));
 
-        match self {
-            HealthState::Dead => HealthState::Dead,
-            HealthState::Ko => if (0 - modifier) < KO_THRESHOLD { HealthState::Dead } else { HealthState::Ko },
-            HealthState::Alive(hit_points) => self.get_new_state_maybe(hit_points as i16 - modifier),
-        }
-    }
-    
-    fn get_new_state_maybe(self, integer_state: i16) -> HealthState {
-        match integer_state {
-            x if x < 0 =>  HealthState::Dead,
-            x if x == 0 =>  HealthState::Ko,
-            _ =>  HealthState::Alive(integer_state as u16),
-        }
-    }
+    let incomplete = r#"[{"name": "Nobody", "armour_class": 10}]"#;
+    let roster = Roster::parse(InputFormat::Json, incomplete).unwrap();
+    assert!(roster.spawn_characters().unwrap_err().to_string().contains("Nobody is missing to_hit"));
+
+    let quack = r#"[{"name": "Quack", "armour_class": 10, "to_hit": 20, "weapon": "1d4", "actions_per_round": 1, "damage": 4, "team": "Heros", "heals": [{"name": "Snake Oil"}]}]"#;
+    let roster = Roster::parse(InputFormat::Json, quack).unwrap();
+    assert!(roster.spawn_characters().unwrap_err().to_string().contains("Quack's Snake Oil needs healing dice or a pool"));
+
+    let garbled = r#"[{"name": "Garbled", "armour_class": 10, "to_hit": 2, "weapon": "1d4", "actions_per_round": 1, "damage": 4, "team": "Heros", "hp": "2dx"}]"#;
+    let roster = Roster::parse(InputFormat::Json, garbled).unwrap();
+    let error = roster.spawn_characters().unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidData);
+    assert!(error.to_string().contains("Garbled has unreadable hit dice: 2dx"));
+
+    let pointblank = r#"[{"name": "Pointblank", "armour_class": 10, "to_hit": 20, "weapon": "1d4", "actions_per_round": 1, "damage": 4, "team": "Heros",
+        "area_attacks": [{"name": "Fireball", "damage": "8d6", "shape": {"kind": "Sphere", "radius": 4}, "save": "Dexterity", "save_dc": 15}]}]"#;
+    let roster = Roster::parse(InputFormat::Json, pointblank).unwrap();
+    assert!(roster.spawn_characters().unwrap_err().to_string().contains("Pointblank's Fireball is a sphere and needs a range"));
 }
@@ -2,20 +2,59 @@ use rand::Rng;
 
 pub fn throw_roll(roll_request_list: &RollRequest) -> u16 {
     let mut roll_result= 0_u16;
-    let mut mod_result = 0_u16;
+    let mut mod_result = 0_i32;
 
     for token in &roll_request_list.request_list {
         match token {
             RequestToken::Dice(roll) => roll_result += roll_dice(roll.number_of_dice, roll.number_of_die_sides).total,
-            RequestToken::Modifier(modifier) => mod_result += modifier.value as u16, 
+            RequestToken::Modifier(modifier) => mod_result += modifier.signed_value(),
             RequestToken::Error => println!("Can't Throw. Your token makes no sense!"),
         };
     }
-    mod_result + roll_result
+    total(roll_result as i32, mod_result)
 }
 
+/// A critical hit: every die is rolled twice, modifiers are added once.
+pub fn throw_critical_roll(roll_request_list: &RollRequest) -> u16 {
+    let mut roll_result= 0_u16;
+    let mut mod_result = 0_i32;
+
+    for token in &roll_request_list.request_list {
+        match token {
+            RequestToken::Dice(roll) => roll_result += roll_dice(roll.number_of_dice * 2, roll.number_of_die_sides).total,
+            RequestToken::Modifier(modifier) => mod_result += modifier.signed_value(),
+            RequestToken::Error => println!("Can't Throw. Your token makes no sense!"),
+        };
+    }
+    total(roll_result as i32, mod_result)
+}
+
+/// The expected total of a request, rounded down as stat blocks do (2d6 is 7, 7d8+14 is 45).
+pub fn average_roll(roll_request_list: &RollRequest) -> u16 {
+    let mut doubled_dice_result = 0_u32;
+    let mut mod_result = 0_i32;
+
+    for token in &roll_request_list.request_list {
+        match token {
+            RequestToken::Dice(roll) => doubled_dice_result += roll.number_of_dice as u32 * (roll.number_of_die_sides as u32 + 1),
+            RequestToken::Modifier(modifier) => mod_result += modifier.signed_value(),
+            RequestToken::Error => println!("Can't average. Your token makes no sense!"),
+        };
+    }
+    total((doubled_dice_result / 2) as i32, mod_result)
+}
+
+/// A modifier can take a total below zero (1d4-2), but a roll never goes negative.
+fn total(dice_result: i32, mod_result: i32) -> u16 {
+    (dice_result + mod_result).clamp(0, u16::MAX as i32) as u16
+}
+
+/// `None` if any part of the request is not dice (`2d6`, `d8`) or a `+N`/`-N` modifier.
 pub fn parse_request(request_string: &str) -> Option<RollRequest> {
     let tokens = tokenize(request_string.to_owned());
+    if tokens.iter().any(|token| matches!(token, RequestToken::Error)) {
+        return None;
+    }
     Some(RollRequest{request_list:tokens})
 }
 
@@ -52,10 +91,19 @@ struct DiceThrowModifier {
     value: u8,
 }
 
+impl DiceThrowModifier {
+    fn signed_value(&self) -> i32 {
+        match self.operator {
+            ModifierOperators::Subtract => -(self.value as i32),
+            _ => self.value as i32,
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 enum ModifierOperators {
     Add,
-    _Subtract,
+    Subtract,
     _Multiply,
     _Divide,
 }
@@ -69,23 +117,40 @@ enum RequestToken {
 
 fn tokenize(mut request_string: String) -> Vec<RequestToken> {
     request_string.retain(|c| !c.is_whitespace());
-    let general_token_list: Vec<&str> = request_string.rsplit('+').collect();
-    let mut request_token_list: Vec<RequestToken> = Vec::with_capacity(general_token_list.len());
-    for general_token in general_token_list {
-        let request_token = make_request_token(general_token);
-        request_token_list.push(request_token);
+    let mut request_token_list: Vec<RequestToken> = Vec::new();
+    let mut operator = ModifierOperators::Add;
+    let mut general_token = String::new();
+    for c in request_string.chars() {
+        match c {
+            '+' | '-' => {
+                request_token_list.push(make_request_token(&general_token, operator));
+                operator = if c == '+' { ModifierOperators::Add } else { ModifierOperators::Subtract };
+                general_token.clear();
+            }
+            _ => general_token.push(c),
+        }
     }
+    request_token_list.push(make_request_token(&general_token, operator));
     request_token_list
 }
 
-fn make_request_token(in_token: &str) -> RequestToken {
+/// Dice can only be added; `2d6-1d4` is an error rather than a guess.
+fn make_request_token(in_token: &str, operator: ModifierOperators) -> RequestToken {
     let token_parts = in_token.split('d').collect::<Vec<&str>>();
 
-    match token_parts.len() {
-        1 => RequestToken::Modifier(DiceThrowModifier {operator: ModifierOperators::Add,
-            value: token_parts[0].parse().unwrap()}),
-        2 => RequestToken::Dice(DiceThrowDescription {number_of_dice: token_parts[0].parse().unwrap_or(1),
-            number_of_die_sides: token_parts[1].parse().unwrap()}),
+    match (token_parts.as_slice(), operator) {
+        ([value], operator) => match value.parse() {
+            Ok(value) => RequestToken::Modifier(DiceThrowModifier {operator, value}),
+            Err(_) => RequestToken::Error,
+        },
+        ([dice, sides], ModifierOperators::Add) => {
+            let number_of_dice = if dice.is_empty() { Ok(1) } else { dice.parse() };
+            match (number_of_dice, sides.parse()) {
+                (Ok(number_of_dice), Ok(number_of_die_sides)) if number_of_die_sides > 0 =>
+                    RequestToken::Dice(DiceThrowDescription {number_of_dice, number_of_die_sides}),
+                _ => RequestToken::Error,
+            }
+        }
         _ => RequestToken::Error,
         }
 }
\ No newline at end of file
@@ -0,0 +1,299 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::io::{Error as IOError, ErrorKind};
+
+use crate::battlefield::{Cover, Position};
+use crate::characterize::{HealthState, Team};
+
+/// Something that happened in a battle. Combatants are named by their place
+/// in the battle order, which never changes once they've joined.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "event")]
+pub enum Event {
+    /// A combatant entered the battle, at the start or part way through.
+    Joined { combatant: usize, name: String, team: Team, health: HealthState, position: Option<Position> },
+    InitiativeRolled { combatant: usize, roll: u8, modifier: i8, initiative: i8 },
+    RoundStarted { round: u8 },
+    Moved { combatant: usize, from: Position, to: Position },
+    /// Every d20 rolled, two with advantage or disadvantage, and the one kept.
+    /// The total is the kept die plus the bonus, less any penalty for
+    /// attacking more than once a turn. `sources` says where any advantage or
+    /// disadvantage came from, including those that cancelled out. Armour
+    /// class includes any bonus for the target's `cover`. An `opportunity`
+    /// attack is made as a reaction to the target moving away.
+    AttackRolled {
+        attacker: usize,
+        target: usize,
+        rolls: Vec<u8>,
+        kept: u8,
+        bonus: u8,
+        #[serde(default)]
+        penalty: u8,
+        total: u8,
+        armour_class: u8,
+        critical: bool,
+        hit: bool,
+        #[serde(default)]
+        sources: Vec<AdvantageSource>,
+        #[serde(default)]
+        cover: Option<Cover>,
+        #[serde(default)]
+        opportunity: bool,
+    },
+    AreaAttack { attacker: usize, name: String, damage: u16 },
+    /// The modifier includes any bonus for `cover` on a Dexterity save.
+    SavingThrow {
+        combatant: usize,
+        roll: u8,
+        modifier: i8,
+        total: u8,
+        dc: u8,
+        saved: bool,
+        #[serde(default)]
+        cover: Option<Cover>,
+    },
+    /// Damage taken and the health left after it.
+    DamageApplied { target: usize, amount: u16, health: HealthState },
+    /// Hit points restored by a spell, potion or feature, and the health after.
+    Healed { healer: usize, target: usize, name: String, amount: u16, health: HealthState },
+    /// Going down, getting back up or dying: a change between alive, knocked
+    /// out and dead.
+    StateChanged { combatant: usize, from: HealthState, to: HealthState },
+    ConditionApplied { combatant: usize, condition: Condition },
+    ConditionEnded { combatant: usize, condition: Condition },
+    BattleEnded { rounds: u8, outcome: String, winner: Option<Team> },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Condition {
+    Dodging,
+    Hidden,
+    Helped,
+    Disengaged,
+    Fled,
+    Surrendered,
+    Prone,
+    Reckless,
+    Outlined,
+}
+
+/// Something giving an attack roll advantage or disadvantage. Any advantage
+/// and any disadvantage cancel out, however many there are of each.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AdvantageSource {
+    AttackerHidden,
+    Helped,
+    RecklessAttack,
+    /// Attacking someone who attacked recklessly since their last turn.
+    TargetReckless,
+    /// Within 5 feet of a prone target.
+    TargetProne,
+    FaerieFire,
+    /// An ally of an attacker with Pack Tactics is next to the target.
+    PackTactics,
+    /// An ally stands on the far side of the target, under the optional
+    /// flanking rule.
+    Flanking,
+    TargetDodging,
+    TargetHidden,
+    /// A ranged attack beyond normal range.
+    LongRange,
+    /// A ranged attack with an enemy standing next to the attacker.
+    Threatened,
+    /// Further than 5 feet from a prone target.
+    TargetProneAtRange,
+    AttackerProne,
+}
+
+impl AdvantageSource {
+    /// True for the sources of advantage, false for those of disadvantage.
+    pub fn is_advantage(self) -> bool {
+        matches!(self, AdvantageSource::AttackerHidden | AdvantageSource::Helped | AdvantageSource::RecklessAttack
+            | AdvantageSource::TargetReckless | AdvantageSource::TargetProne | AdvantageSource::FaerieFire
+            | AdvantageSource::PackTactics | AdvantageSource::Flanking)
+    }
+}
+
+impl fmt::Display for AdvantageSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            AdvantageSource::AttackerHidden => "unseen",
+            AdvantageSource::Helped => "helped",
+            AdvantageSource::RecklessAttack => "reckless",
+            AdvantageSource::TargetReckless => "target reckless",
+            AdvantageSource::TargetProne => "target prone",
+            AdvantageSource::FaerieFire => "faerie fire",
+            AdvantageSource::PackTactics => "pack tactics",
+            AdvantageSource::Flanking => "flanking",
+            AdvantageSource::TargetDodging => "target dodging",
+            AdvantageSource::TargetHidden => "target hidden",
+            AdvantageSource::LongRange => "long range",
+            AdvantageSource::Threatened => "enemy adjacent",
+            AdvantageSource::TargetProneAtRange => "target prone at range",
+            AdvantageSource::AttackerProne => "attacker prone",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+/// Where a battle's events go. Off by default, so a battle that isn't being
+/// recorded never builds an event.
+#[derive(Debug, Clone, Default)]
+pub struct EventLog {
+    events: Option<Vec<Event>>,
+}
+
+impl EventLog {
+    pub fn recording() -> Self {
+        EventLog { events: Some(Vec::new()) }
+    }
+
+    pub fn record(&mut self, event: impl FnOnce() -> Event) {
+        if let Some(events) = self.events.as_mut() {
+            events.push(event());
+        }
+    }
+
+    /// Records `condition` starting or ending, if `now` differs from `was`.
+    pub fn condition(&mut self, combatant: usize, condition: Condition, was: bool, now: bool) {
+        match (was, now) {
+            (false, true) => self.record(|| Event::ConditionApplied { combatant, condition }),
+            (true, false) => self.record(|| Event::ConditionEnded { combatant, condition }),
+            _ => (),
+        }
+    }
+
+    pub fn events(&self) -> &[Event] {
+        self.events.as_deref().unwrap_or(&[])
+    }
+
+    /// One JSON object per line.
+    pub fn to_json_lines(&self) -> String {
+        self.events().iter()
+            .map(|event| serde_json::to_string(event).expect("events always serialize"))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+pub fn from_json_lines(lines: &str) -> Result<Vec<Event>, IOError> {
+    lines.lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(number, line)| serde_json::from_str(line)
+            .map_err(|error| IOError::new(ErrorKind::InvalidData, format!("Event {}: {}", number + 1, error))))
+        .collect()
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CombatantState {
+    pub name: String,
+    pub team: Team,
+    pub initiative: Option<i8>,
+    pub health: HealthState,
+    pub position: Option<Position>,
+    pub conditions: BTreeSet<Condition>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BattleState {
+    pub round: u8,
+    pub combatants: Vec<CombatantState>,
+    pub ended: Option<String>,
+}
+
+/// The battle as it stood after the first `steps` events, or an error for
+/// the first event naming a combatant that never joined.
+pub fn replay(events: &[Event], steps: usize) -> Result<BattleState,IOError> {
+    let mut state = BattleState::default();
+    for (number, event) in events.iter().take(steps).enumerate() {
+        state.apply(event)
+            .map_err(|error| IOError::new(ErrorKind::InvalidData, format!("Event {} refers to {}", number + 1, error)))?;
+    }
+    Ok(state)
+}
+
+impl BattleState {
+    pub fn apply(&mut self, event: &Event) -> Result<(),IOError> {
+        match event {
+            Event::Joined { name, team, health, position, .. } => self.combatants.push(CombatantState {
+                name: name.clone(),
+                team: *team,
+                initiative: None,
+                health: *health,
+                position: *position,
+                conditions: BTreeSet::new(),
+            }),
+            Event::InitiativeRolled { combatant, initiative, .. } => self.combatant(*combatant)?.initiative = Some(*initiative),
+            Event::RoundStarted { round } => self.round = *round,
+            Event::Moved { combatant, to, .. } => self.combatant(*combatant)?.position = Some(*to),
+            Event::DamageApplied { target, health, .. } | Event::Healed { target, health, .. } => self.combatant(*target)?.health = *health,
+            Event::StateChanged { combatant, to, .. } => self.combatant(*combatant)?.health = *to,
+            Event::ConditionApplied { combatant, condition } => { self.combatant(*combatant)?.conditions.insert(*condition); }
+            Event::ConditionEnded { combatant, condition } => { self.combatant(*combatant)?.conditions.remove(condition); }
+            Event::BattleEnded { outcome, .. } => self.ended = Some(outcome.clone()),
+            Event::AttackRolled { .. } | Event::AreaAttack { .. } | Event::SavingThrow { .. } => (),
+        }
+        Ok(())
+    }
+
+    fn combatant(&mut self, combatant: usize) -> Result<&mut CombatantState,IOError> {
+        self.combatants.get_mut(combatant)
+            .ok_or_else(|| IOError::new(ErrorKind::InvalidData, format!("unknown combatant {}", combatant)))
+    }
+}
+
+impl fmt::Display for BattleState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Round {}", self.round)?;
+        for (i, combatant) in self.combatants.iter().enumerate() {
+            write!(f, "{}: {} ({}) {:?}", i, combatant.name, combatant.team, combatant.health)?;
+            if let Some(position) = combatant.position {
+                write!(f, " at {:?}", position)?;
+            }
+            if !combatant.conditions.is_empty() {
+                write!(f, " {:?}", combatant.conditions)?;
+            }
+            writeln!(f)?;
+        }
+        match &self.ended {
+            Some(outcome) => write!(f, "Ended: {}", outcome),
+            None => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn replay_test() {
+    let events = vec![
+        Event::Joined { combatant: 0, name: "Hero".to_string(), team: Team::Heros, health: HealthState::Alive(5), position: None },
+        Event::Joined { combatant: 1, name: "Villan".to_string(), team: Team::Villains, health: HealthState::Alive(3), position: None },
+        Event::RoundStarted { round: 1 },
+        Event::ConditionApplied { combatant: 1, condition: Condition::Dodging },
+        Event::AttackRolled { attacker: 0, target: 1, rolls: vec![15, 4], kept: 4, bonus: 2, penalty: 0, total: 6, armour_class: 12, critical: false, hit: false, sources: vec![AdvantageSource::TargetDodging], cover: None, opportunity: false },
+        Event::DamageApplied { target: 1, amount: 3, health: HealthState::Ko },
+        Event::StateChanged { combatant: 1, from: HealthState::Alive(3), to: HealthState::Ko },
+        Event::BattleEnded { rounds: 1, outcome: "Heros".to_string(), winner: Some(Team::Heros) },
+    ];
+    let mut log = EventLog::recording();
+    for event in events.iter().cloned() {
+        log.record(|| event);
+    }
+    let read_back = from_json_lines(&log.to_json_lines()).unwrap();
+    assert_eq!(read_back, events);
+
+    let before_the_blow = replay(&read_back, 5).unwrap();
+    assert_eq!(before_the_blow.combatants[1].health, HealthState::Alive(3));
+    assert!(before_the_blow.combatants[1].conditions.contains(&Condition::Dodging));
+    let after = replay(&read_back, read_back.len()).unwrap();
+    assert_eq!(after.combatants[1].health, HealthState::Ko);
+    assert_eq!(after.ended.as_deref(), Some("Heros"));
+    let stray = [events[0].clone(), Event::Moved { combatant: 3, from: (0, 0), to: (1, 1) }];
+    assert_eq!(replay(&stray, stray.len()).unwrap_err().to_string(), "Event 2 refers to unknown combatant 3");
+
+    let mut quiet = EventLog::default();
+    quiet.record(|| panic!("nothing is built when not recording"));
+    assert!(quiet.events().is_empty());
+    assert!(from_json_lines("{\"event\": \"Nonsense\"}").is_err());
+}
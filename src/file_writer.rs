@@ -22,7 +22,7 @@ impl FileWriterHandle {
 }
 
 pub fn new(file_name: &str) -> FileWriter {
-    let file_result = OpenOptions::new().create(true).write(true).append(true).open(file_name);
+    let file_result = OpenOptions::new().create(true).append(true).open(file_name);
 
     match file_result {
         Ok(file) => FileWriter::Ready(FileWriterHandle{ file }),
@@ -1,8 +1,12 @@
+// the original tests compare against literal bools
+#![cfg_attr(test, allow(clippy::bool_assert_comparison, clippy::single_match))]
+
 use std::{fmt};
 use std::{io::Error, thread};
 // use dice_thrower::RollRequest;
 // use rand::Rng;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
 use std::sync::mpsc::{SendError, Sender, channel};
 
 use crate::file_writer::FileWriter;
@@ -10,18 +14,59 @@ use crate::file_writer::FileWriter;
 mod dice_thrower;
 mod file_writer;
 mod characterize;
-use characterize::{CharacterStruct, HealthState, Team, load_players};
+mod targeting;
+mod policy;
+mod battlefield;
+mod event_log;
+mod narrative;
+mod ruleset;
+use characterize::{AreaAttack, CharacterStruct, Encounter, MoraleResponse, Reinforcement, Rest, SaveAbility, Stage, Trigger, HealthState, HitPointMode, HitPoints, Team, load_encounter};
+#[cfg(test)]
+use characterize::load_players;
+use battlefield::{AreaShape, Battlefield, Cover, Position, distance};
+use targeting::{Targeting, targeting_label};
+use policy::{Action, BattleView, Policies};
 use dice_thrower::RollRequest;
+use event_log::{AdvantageSource, Condition, Event, EventLog};
+use ruleset::{Rules, Ruleset};
 
+const DEFAULT_INPUT_FILE: &str = "./input/temp.json";
 const BATTLE_COLLECTION_SUMMARY_FILE: &str = "./output/bc_summary.out";
 const BATTLE_COLLECTION_ACCUMULATION_FILE: &str = "./output/bc_accumulation.out";
+const DAY_SUMMARY_FILE: &str = "./output/day_summary.out";
 const DESIRED_ITERATIONS: u32 = 10_000_000;
 const THREADS_DESIRED: u32 = 10;
 const THREAD_ITERATIONS: u32 = DESIRED_ITERATIONS/THREADS_DESIRED;
 const WRITE_TO_FILE_TRIGGER: u32 = 50_000;
+const DEFAULT_MAX_ROUNDS: u8 = 100;
 
 fn main() -> Result<(),String> {
-    let player_vec= load_players("./input/temp.json").expect("Main");
+    let args = parse_args(std::env::args().skip(1).collect())?;
+    if let Some(log_file) = &args.replay {
+        return replay_log(log_file, args.step);
+    }
+    let mut encounter = load_encounter(&args.input_file, args.level).expect("Main");
+    if args.max_rounds.is_some() {
+        encounter.max_rounds = args.max_rounds;
+    }
+    if let Some(rules) = args.rules {
+        encounter.rules = rules;
+    }
+    encounter.flanking |= args.flanking;
+    for player in encounter.characters.iter_mut() {
+        if let Some(targeting) = args.targeting_for(player.team) {
+            player.targeting = targeting;
+        }
+    }
+    // register any policies of your own here for characters to name with `policy`
+    let policies = Arc::new(Policies::default());
+    policies.check(encounter.every_character())?;
+    if let Some(log_file) = &args.event_log {
+        return record_battle(&encounter, &policies, log_file);
+    }
+    if let Some(battles) = args.narrate {
+        return narrate_battles(&encounter, &policies, battles);
+    }
 
     let mut thread_list: Vec<thread::JoinHandle<()>> = Vec::with_capacity(6);
     let (sender, receiver):(Sender<SendBuffer>, std::sync::mpsc::Receiver<_>) = channel();
@@ -30,8 +75,8 @@ fn main() -> Result<(),String> {
         loop {
             let rx_res = receiver.recv();
             match rx_res {
-                Ok(foo) => {
-                    let res= write_to_file(&foo.buffer , &foo.file_name);
+                Ok(send_buffer) => {
+                    let res= write_to_file(&send_buffer.buffer , send_buffer.file_name);
                     match res {
                         Err(error) => println!("Received Write Thread error: {}", error),
                         Ok(_) => continue,
@@ -43,13 +88,18 @@ fn main() -> Result<(),String> {
     });
 
     for i in 0..THREADS_DESIRED as usize{
-        let local_player_vec: Vec<CharacterStruct> = player_vec.clone();
+        let local_encounter: Encounter = encounter.clone();
+        let policies = policies.clone();
         let sender = sender.clone();
         let name = format!("Sender-{}",i);
         let builder = thread::Builder::new().name(name);
 
         let handle: thread::JoinHandle<()> = builder.spawn(move || {
-            battle(&local_player_vec, THREAD_ITERATIONS, i as u8, ReportOutputLevel::None, sender).unwrap();
+            if local_encounter.day.is_empty() {
+                battle(&local_encounter, &policies, THREAD_ITERATIONS, i as u8, ReportOutputLevel::None, sender).unwrap();
+            } else {
+                adventuring_day(&local_encounter, &policies, THREAD_ITERATIONS, i as u8, ReportOutputLevel::None, sender).unwrap();
+            }
         }).unwrap();   
         thread_list.push(handle);
     }
@@ -62,6 +112,94 @@ fn main() -> Result<(),String> {
     
     Ok(())
 }
+#[derive(Debug, PartialEq)]
+struct Args {
+    input_file: String,
+    level: Option<u8>,
+    targeting: Vec<(Option<Team>, Targeting)>,
+    max_rounds: Option<u8>,
+    rules: Option<Rules>,
+    flanking: bool,
+    event_log: Option<String>,
+    replay: Option<String>,
+    step: Option<usize>,
+    narrate: Option<u32>,
+}
+
+impl Args {
+    /// The last `--targeting` given for `team`, or for everyone.
+    fn targeting_for(&self, team: Team) -> Option<Targeting> {
+        self.targeting.iter().rev()
+            .find(|(for_team, _)| for_team.is_none_or(|for_team| for_team == team))
+            .map(|(_, targeting)| *targeting)
+    }
+}
+
+/// `montednd [input file] [--level N] [--max-rounds N] [--ruleset RULES] [--flanking] [--targeting [TEAM=]STRATEGY]...
+/// [--event-log FILE] [--narrate N]` or `montednd --replay FILE [--step N]`,
+/// where `--level` instantiates every character with a class at level N,
+/// `--max-rounds` calls a battle a timeout after N rounds, `--ruleset` picks
+/// the game system, `--flanking` turns on the optional flanking rule and
+/// `--targeting` overrides the strategy of everyone, or of one team. `--event-log` runs a
+/// single battle and writes its events to FILE, and `--replay` prints the
+/// battle recorded in FILE as it stood after N events, or at the end.
+/// `--narrate` tells N sample battles as play-by-play instead of simulating.
+fn parse_args(args: Vec<String>) -> Result<Args,String> {
+    let mut parsed = Args {
+        input_file: DEFAULT_INPUT_FILE.to_string(),
+        level: None,
+        targeting: Vec::new(),
+        max_rounds: None,
+        rules: None,
+        flanking: false,
+        event_log: None,
+        replay: None,
+        step: None,
+        narrate: None,
+    };
+    let mut args = args.into_iter();
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--level" => {
+                let value = args.next().ok_or("--level needs a value")?;
+                parsed.level = Some(value.parse().map_err(|_| format!("Invalid level: {}", value))?);
+            }
+            "--max-rounds" => {
+                let value = args.next().ok_or("--max-rounds needs a value")?;
+                parsed.max_rounds = Some(value.parse().ok().filter(|rounds| *rounds > 0)
+                    .ok_or_else(|| format!("Invalid max rounds: {}", value))?);
+            }
+            "--ruleset" => {
+                let value = args.next().ok_or("--ruleset needs a value")?;
+                parsed.rules = Some(value.parse()?);
+            }
+            "--flanking" => parsed.flanking = true,
+            "--event-log" => parsed.event_log = Some(args.next().ok_or("--event-log needs a file")?),
+            "--replay" => parsed.replay = Some(args.next().ok_or("--replay needs a file")?),
+            "--step" => {
+                let value = args.next().ok_or("--step needs a value")?;
+                parsed.step = Some(value.parse().map_err(|_| format!("Invalid step: {}", value))?);
+            }
+            "--narrate" => {
+                let value = args.next().ok_or("--narrate needs a number of battles")?;
+                parsed.narrate = Some(value.parse().ok().filter(|battles| *battles > 0)
+                    .ok_or_else(|| format!("Invalid number of battles: {}", value))?);
+            }
+            "--targeting" => {
+                let value = args.next().ok_or("--targeting needs a value")?;
+                let (team, strategy) = match value.split_once('=') {
+                    Some((team, strategy)) => (Some(team.parse()?), strategy),
+                    None => (None, value.as_str()),
+                };
+                parsed.targeting.push((team, strategy.parse()?));
+            }
+            _ => parsed.input_file = arg,
+        }
+    }
+    Ok(parsed)
+}
+
 #[derive(Default)]
 struct RequestCache {
     cache: HashMap<String,RollRequest>,
@@ -90,7 +228,7 @@ fn write_to_file(buffer: &str, file_name: &str) -> Result<String,std::io::Error>
 
     match writer {
         FileWriter::Ready(file) => 
-            match file.write_buffer(&buffer){
+            match file.write_buffer(buffer){
                     Ok(_) => Ok(String::from("Okay")),
                     Err(error) => Err(handle_file_error(error)),
                 },
@@ -104,14 +242,10 @@ fn handle_file_error(error: Error ) -> Error {
 }
 
 impl CharacterStruct {
-    fn select_target(&self, combatant_list: &[BattleOrder]) -> Option<usize> {
-        for (i, target) in combatant_list.iter().enumerate() {
-            if target.character.team != self.team &&
-                target.character.is_concious() {
-                    return Some(i)
-            }
-        }
-        None
+    /// Twice the average damage per round, assuming every attack lands;
+    /// doubled so that half points survive integer comparison.
+    fn expected_damage(&self) -> u16 {
+        (self.damage as u16 + 1) * self.actions_per_round.max(1) as u16
     }
 
     fn is_concious(&self) ->  bool {
@@ -121,60 +255,58 @@ impl CharacterStruct {
         }
     }
 
-    fn do_some_damage(&self) -> DamageResult {
-        let roll_request = dice_thrower::parse_request(&self.weapon.to_string()).unwrap();
-
-        DamageResult {
-            damage: dice_thrower::throw_roll(&roll_request) as u8,
+    /// Lowest natural roll that crits: an explicit `crit_range`, else 19 or
+    /// 18 for Improved and Superior Critical, else 20.
+    fn crit_threshold(&self) -> u8 {
+        if let Some(crit_range) = self.crit_range {
+            return crit_range;
         }
+        if self.features.iter().any(|feature| feature == "Superior Critical") { 18 }
+        else if self.features.iter().any(|feature| feature == "Improved Critical") { 19 }
+        else { 20 }
     }
 
-    fn is_attack_successful(&self, attack_result: &AttackResult ) -> bool {
-        if attack_result.attack_roll > self.armour_class {
-            return true
-        }
-            false
+    /// Barbarians with Reckless Attack use it on every melee attack.
+    fn attacks_recklessly(&self) -> bool {
+        self.range.is_none() && self.features.iter().any(|feature| feature == "Reckless Attack")
     }
 
-    fn make_attack(&self) -> AttackResult {
-        let roll_request = dice_thrower::parse_request(
-            &format!("d{}",&self.to_hit.to_string()));
+    fn has_pack_tactics(&self) -> bool {
+        self.features.iter().any(|feature| feature == "Pack Tactics")
+    }
 
-        AttackResult { 
-            attack_roll: dice_thrower::throw_roll(&roll_request.unwrap()) as u8,
-            _roll_string: self.weapon.clone(),
+    fn roll_hit_points(&mut self, request_cache: &mut RequestCache) {
+        if let (Some(HitPoints::Dice(hit_dice)), HitPointMode::Roll) = (&self.hp, self.hp_mode) {
+            let request = request_cache.get_roll_request(hit_dice);
+            self.hs2 = HealthState::Alive(dice_thrower::throw_roll(&request).max(1));
         }
     }
 
     fn _defend_attack(){}
 
-    fn take_damage(mut self, damage: u16) -> Self {
-        self.hs2 = self.hs2.update_health_state(damage as i16);
+    fn take_damage(mut self, damage: u16, ruleset: &dyn Ruleset) -> Self {
+        self.hs2 = ruleset.take_damage(self.hs2, damage);
         self
     }
 
     fn _react_to(){}
 }
 
-fn battle( players: &[CharacterStruct], battle_count: u32, arena_id: u8, report_level: ReportOutputLevel, sender: Sender<SendBuffer>) -> Result<String, SendError<SendBuffer>> {
+fn battle( encounter: &Encounter, policies: &Arc<Policies>, battle_count: u32, arena_id: u8, report_level: ReportOutputLevel, sender: Sender<SendBuffer>) -> Result<String, SendError<SendBuffer>> {
     const DUMP_INCREMENT: u32 = WRITE_TO_FILE_TRIGGER;
-    let battle_order_list = make_battle_order_list(players, &report_level);
     let mut battle_result_collection = BattleResultCollection {
-        battle_order_list: battle_order_list.battle_order_list.clone(),
         battle_count,
         arena_id,
+        targeting: targeting_label(&encounter.characters),
         ..Default::default()
     };
 
-    let initiative_winner = &battle_order_list.battle_order_list.iter().max_by_key(|p| p.initative_roll).unwrap();
-    battle_result_collection.battle_order_list = battle_order_list.battle_order_list.clone();
     let mut dump_counter = DUMP_INCREMENT;
 
     for battle_num in 0..battle_count {
-        let mut current_battle = battle_order_list.clone();
+        let mut current_battle = new_battle(encounter, policies);
         current_battle = current_battle.run_battle(battle_num, &report_level);
         current_battle.battle_result.battle_id = format!("{}{:0>6}", arena_id, battle_num);        
-        current_battle.battle_result.initiative_winner = initiative_winner.team.to_string();
         battle_result_collection.battle_result_list.push(current_battle.battle_result.clone());
 
         if battle_num > dump_counter {        
@@ -199,6 +331,141 @@ fn battle( players: &[CharacterStruct], battle_count: u32, arena_id: u8, report_
     Ok(String::from("Okay"))
 }
 
+fn new_battle(encounter: &Encounter, policies: &Arc<Policies>) -> BattleOrderList {
+    let mut battle = make_battle_order_list(&encounter.characters, encounter.rules, policies);
+    battle.battlefield = encounter.battlefield.clone();
+    battle.max_rounds = encounter.max_rounds;
+    battle.flanking = encounter.flanking;
+    battle.reinforcements = encounter.reinforcements.clone();
+    battle
+}
+
+/// Runs one battle of `encounter` with every event recorded.
+fn recorded_battle(encounter: &Encounter, policies: &Arc<Policies>, battle_num: u32) -> Result<BattleOrderList, String> {
+    if !encounter.day.is_empty() {
+        return Err("Only single battles are recorded, not an adventuring day".to_string());
+    }
+    let mut battle = new_battle(encounter, policies);
+    battle.event_log = EventLog::recording();
+    Ok(battle.run_battle(battle_num, &ReportOutputLevel::None))
+}
+
+/// Writes the events of one battle to `log_file` as JSON Lines.
+fn record_battle(encounter: &Encounter, policies: &Arc<Policies>, log_file: &str) -> Result<(), String> {
+    let battle = recorded_battle(encounter, policies, 0)?;
+    std::fs::write(log_file, battle.event_log.to_json_lines() + "\n")
+        .map_err(|error| format!("{}: {}", log_file, error))?;
+    println!("{} events written to {}", battle.event_log.events().len(), log_file);
+    Ok(())
+}
+
+fn narrate_battles(encounter: &Encounter, policies: &Arc<Policies>, battles: u32) -> Result<(), String> {
+    for battle_num in 0..battles {
+        let battle = recorded_battle(encounter, policies, battle_num)?;
+        if battles > 1 {
+            println!("Battle {}", battle_num + 1);
+        }
+        for line in narrative::narrate(battle.event_log.events()) {
+            println!("{}", line);
+        }
+        println!();
+    }
+    Ok(())
+}
+
+fn replay_log(log_file: &str, step: Option<usize>) -> Result<(), String> {
+    let lines = std::fs::read_to_string(log_file).map_err(|error| format!("{}: {}", log_file, error))?;
+    let events = event_log::from_json_lines(&lines).map_err(|error| format!("{}: {}", log_file, error))?;
+    let state = event_log::replay(&events, step.unwrap_or(events.len())).map_err(|error| format!("{}: {}", log_file, error))?;
+    println!("{}", state);
+    Ok(())
+}
+
+fn adventuring_day(encounter: &Encounter, policies: &Arc<Policies>, day_count: u32, arena_id: u8, report_level: ReportOutputLevel, sender: Sender<SendBuffer>) -> Result<String, SendError<SendBuffer>> {
+    const DUMP_INCREMENT: u32 = WRITE_TO_FILE_TRIGGER;
+    let mut day_result_collection = DayResultCollection {
+        arena_id,
+        day_count,
+        stages: encounter.day.iter().map(|stage| stage.name.clone()).collect(),
+        ..Default::default()
+    };
+
+    let mut dump_counter = DUMP_INCREMENT;
+
+    for day_num in 0..day_count {
+        day_result_collection.day_result_list.push(run_day(encounter, policies, day_num, &report_level));
+
+        if day_num > dump_counter {
+            dump_counter += DUMP_INCREMENT;
+            let summary = day_result_collection.summarize().unwrap();
+            let send_buffer = SendBuffer {
+                buffer: format!("{}",summary),
+                file_name: DAY_SUMMARY_FILE,
+            };
+            write_buffer(send_buffer, &sender)?;
+
+            day_result_collection.day_result_list.clear();
+        }
+    }
+    Ok(String::from("Okay"))
+}
+
+/// Takes the party, the Heros among `encounter.characters`, through each
+/// encounter of the day in turn. Hit points, hit dice and area attack uses
+/// carry from one fight to the next, topped up only by the rests taken. The
+/// day ends at the first fight the party doesn't win.
+fn run_day(encounter: &Encounter, policies: &Arc<Policies>, day_num: u32, report_level: &ReportOutputLevel) -> DayResult {
+    let mut request_cache: RequestCache = Default::default();
+    let mut party: Vec<CharacterStruct> = encounter.characters.iter().filter(|member| member.team == Team::Heros).cloned().collect();
+    for member in party.iter_mut() {
+        member.roll_hit_points(&mut request_cache);
+        member.hp = Some(HitPoints::Fixed(member.hit_points()));
+    }
+    let rested = party.clone();
+
+    for (stage_number, stage) in encounter.day.iter().enumerate() {
+        let (battle, fighting) = stage_battle(encounter, stage, &party, &rested, policies);
+        let battle = battle.run_battle(day_num, report_level);
+
+        if battle.battle_result.outcome.winner() != Some(Team::Heros) {
+            return DayResult { failed_at: Some(stage_number) };
+        }
+        for order in battle.battle_order_list.iter() {
+            if let Some(member) = order.roster_index.and_then(|index| fighting.get(index)) {
+                party[*member] = order.character.clone();
+            }
+        }
+        for (member, fresh) in party.iter_mut().zip(&rested) {
+            match stage.rest {
+                Some(Rest::Short) => member.short_rest(fresh),
+                Some(Rest::Long) => member.long_rest(fresh),
+                None => (),
+            }
+        }
+    }
+    DayResult { failed_at: None }
+}
+
+/// The fight at `stage` for the `party` members still alive, whose indexes
+/// into `party` come back alongside it. Everyone keeps the maximum hit points
+/// they had `rested` at the start of the day, however wounded they arrive.
+fn stage_battle(encounter: &Encounter, stage: &Stage, party: &[CharacterStruct], rested: &[CharacterStruct], policies: &Arc<Policies>) -> (BattleOrderList, Vec<usize>) {
+    let fighting: Vec<usize> = (0..party.len()).filter(|member| party[*member].hs2 != HealthState::Dead).collect();
+    let mut combatants: Vec<CharacterStruct> = fighting.iter().map(|member| party[*member].clone()).collect();
+    combatants.extend(stage.characters.iter().cloned());
+    let mut battle = make_battle_order_list(&combatants, encounter.rules, policies);
+    for order in battle.battle_order_list.iter_mut() {
+        if let Some(member) = order.roster_index.and_then(|index| fighting.get(index)) {
+            order.max_hp = rested[*member].hit_points();
+        }
+    }
+    battle.battlefield = stage.battlefield.clone();
+    battle.max_rounds = encounter.max_rounds;
+    battle.flanking = encounter.flanking;
+    battle.reinforcements = stage.reinforcements.clone();
+    (battle, fighting)
+}
+
 fn write_buffer(send_buffer: SendBuffer, sender: &Sender<SendBuffer>) -> Result<String,SendError<SendBuffer>> {
     if let Err(error) = sender.send(send_buffer){
         Err(error)
@@ -209,75 +476,144 @@ struct SendBuffer {
     file_name: &'static str,
 }
 
-fn make_battle_order_list(players: &[characterize::CharacterStruct], report_level: &ReportOutputLevel) -> BattleOrderList {
+fn make_battle_order_list(players: &[characterize::CharacterStruct], rules: Rules, policies: &Arc<Policies>) -> BattleOrderList {
     let mut battle_order_list: Vec<BattleOrder> = Vec::with_capacity(players.len());
-    let initiative_die = "1d20".to_string();
-    let roll_request = dice_thrower::parse_request(&initiative_die).unwrap();
+    let mut request_cache: RequestCache = Default::default();
     let battle_result: BattleResult = Default::default();
     
-    for player in players {
-        let initative_roll = dice_thrower::throw_roll(&roll_request);
-        
-        let order = BattleOrder {
-            initative_roll: initative_roll as u8,
-            character: player.clone(),
-            team: player.team,
-        };
+    for (roster_index, player) in players.iter().enumerate() {
+        let initiative = rules.ruleset().initiative(player, &battle_order_list, &mut request_cache);
+        let mut order = enter_battle(player, initiative);
+        order.roster_index = Some(roster_index);
         battle_order_list.push(order);
     }
-    battle_order_list.sort_by(|a,b| b.initative_roll.cmp(&a.initative_roll));
-    match report_level {
-        ReportOutputLevel::Summary => BattleOrderList { 
-            battle_order_list, 
-            report_level: ReportOutputLevel::Summary,
-            battle_result },
-        ReportOutputLevel::Accumulate => BattleOrderList {
-            battle_order_list,
-            report_level: ReportOutputLevel::Accumulate,
-            battle_result },
-        ReportOutputLevel::None => BattleOrderList {
-            battle_order_list,
-            report_level: ReportOutputLevel::None,
-            battle_result },
+    // ties go to the higher DEX, then to whoever wins a coin toss
+    battle_order_list.sort_by_cached_key(|b| std::cmp::Reverse(
+        (b.initative_roll, b.character.dexterity_modifier, rand::random::<u32>())));
+    BattleOrderList {
+        battle_order_list,
+        battle_result,
+        rules,
+        policies: policies.clone(),
+        ..Default::default()
     }
 }
 
-#[derive(Clone, Copy, Debug)]
-enum ReportOutputLevel {
-    None,
-    Summary,
-    Accumulate,
+fn enter_battle(player: &CharacterStruct, initiative: i8) -> BattleOrder {
+    BattleOrder {
+        initative_roll: initiative,
+        character: player.clone(),
+        team: player.team,
+        ..Default::default()
+    }
 }
 
-impl Default for ReportOutputLevel {
-    fn default() -> Self { ReportOutputLevel::None }
+// nothing on the command line asks for turn results yet
+#[cfg_attr(not(test), allow(dead_code))]
+#[derive(Clone, Copy, Debug, Default)]
+enum ReportOutputLevel {
+    #[default]
+    None,
+    /// Keeps every turn's action results in the battle result.
+    Turns,
 }
 
-#[derive(Clone, Debug, Copy)]
+/// Also the four degrees of success, from critical failure to critical
+/// success, for rulesets that have them.
+#[derive(Clone, Debug, Copy, PartialEq, Eq)]
 enum ActionResultType {
-    _CritMiss,
+    CritMiss,
     Miss,
     Hit,
-    _CritHit,
+    CritHit,
+    NoRoll,
 }
 
 #[derive(Clone, Debug, Copy)]
 enum ActionType {
     Attack,
-    _Dodge,
-    _Cast,
-    _Dash,
+    Dodge,
+    Cast,
+    Dash,
+    Disengage,
+    Hide,
+    Help,
+    Heal,
+    Flee,
+    Surrender,
+    Summon,
+    OpportunityAttack,
     _NoAction,
     _NoTarget,
 }
+/// How a battle ended. A draw is everyone going down together; a stalemate
+/// is a fight nobody left can win; a timeout is running out of rounds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+enum BattleOutcome {
+    Victory(Team),
+    /// Won with some of the losers fleeing or surrendering rather than falling.
+    Rout(Team),
+    #[default]
+    Draw,
+    Stalemate,
+    Timeout,
+}
+
+impl BattleOutcome {
+    fn winner(&self) -> Option<Team> {
+        match self {
+            BattleOutcome::Victory(team) | BattleOutcome::Rout(team) => Some(*team),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for BattleOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BattleOutcome::Victory(team) => write!(f, "{}", team),
+            BattleOutcome::Rout(team) => write!(f, "{} by rout", team),
+            other => write!(f, "{:?}", other),
+        }
+    }
+}
+
+/// Roll two dice and keep the higher, or the lower; having both cancels out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RollMode {
+    Normal,
+    Advantage,
+    Disadvantage,
+}
+
+impl RollMode {
+    fn combine(advantage: bool, disadvantage: bool) -> Self {
+        match (advantage, disadvantage) {
+            (true, false) => RollMode::Advantage,
+            (false, true) => RollMode::Disadvantage,
+            _ => RollMode::Normal,
+        }
+    }
+
+    fn resolve(sources: &[AdvantageSource]) -> Self {
+        RollMode::combine(
+            sources.iter().any(|source| source.is_advantage()),
+            sources.iter().any(|source| !source.is_advantage()))
+    }
+}
+
 struct AttackResult {
     attack_roll: u8,
+    natural_roll: u8,
+    /// The die set aside when rolling with advantage or disadvantage.
+    other_roll: Option<u8>,
+    critical: bool,
     _roll_string: String,
 }
 
 #[derive(Default, Clone, Debug)]
 struct DamageResult{
-    damage: u8,
+    damage: u16,
     // hit_type: ActionResultType,
 }
 
@@ -288,29 +624,42 @@ struct TurnResult {
 }
 
 struct TurnResultSummary {
-    _action_count: u8,
-    _number_of_hits: u8,
-    _damage_done: u8,
+    turn_number: u8,
+    action_count: u8,
+    number_of_hits: u8,
+    number_of_crits: u8,
+    damage_done: u16,
+}
+
+impl fmt::Display for TurnResultSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{},{},{},{},{}", self.turn_number, self.action_count, self.number_of_hits, self.number_of_crits, self.damage_done)
+    }
 }
 
 impl Summary<TurnResultSummary> for TurnResult {
     fn summarize(&self) -> Option<TurnResultSummary>{
-        let _action_count: u8 = self.action_results.len() as u8;
-        let _number_of_hits = self.action_results.iter().fold(
+        let action_count: u8 = self.action_results.len() as u8;
+        let number_of_hits = self.action_results.iter().fold(
             0, |i, action| 
             match action.action_result {
                 ActionResultType::Hit => i + 1,
-                ActionResultType::_CritHit => i + 1,
+                ActionResultType::CritHit => i + 1,
                 _ => i,
             }
         );
-        let _damage_done: u8 = self.action_results.iter().fold(
-            0, |i: u8, action| 
-            i + action.action_damage as u8);
+        let number_of_crits = self.action_results.iter()
+            .filter(|action| matches!(action.action_result, ActionResultType::CritHit))
+            .count() as u8;
+        let damage_done: u16 = self.action_results.iter().fold(
+            0, |i: u16, action| 
+            i.saturating_add(action.action_damage));
         Some(TurnResultSummary {
-            _action_count,
-            _number_of_hits,
-            _damage_done,
+            turn_number: self.turn_number,
+            action_count,
+            number_of_hits,
+            number_of_crits,
+            damage_done,
         })
     }
 }
@@ -334,6 +683,8 @@ struct ActionResult  {
     action_roll: u8,
     action_result: ActionResultType,
     action_damage: u16,
+    /// Cover the target had against the attack or from the area.
+    cover: Option<Cover>,
 }
 
 impl fmt::Display for ActionResult {
@@ -353,32 +704,54 @@ impl fmt::Display for ActionResult {
 #[derive(Clone, Debug, Default)]
 struct BattleOrderList {
     battle_order_list: Vec<BattleOrder>,
-    report_level: ReportOutputLevel,
     battle_result: BattleResult,
+    policies: Arc<Policies>,
+    battlefield: Option<Arc<Battlefield>>,
+    max_rounds: Option<u8>,
+    /// Whether the optional flanking rule is in play.
+    flanking: bool,
+    /// Reinforcements yet to arrive.
+    reinforcements: Vec<Reinforcement>,
+    event_log: EventLog,
+    rules: Rules,
 }
 
 impl BattleOrderList {
     fn run_battle(mut self, battle_num: u32, report_level: &ReportOutputLevel) -> Self {
-        let mut winning_result = false;
+        let max_rounds = self.max_rounds.unwrap_or(DEFAULT_MAX_ROUNDS).max(1);
         let mut turn_number: u8 = 1;
         let mut request_cache: RequestCache = Default::default();
-        let mut turn_result: TurnResult = Default::default();
         self.battle_result = BattleResult {
             battle_id: battle_num.to_string(),
-            initiative_winner: self.battle_order_list[0].character.name.clone(),
+            initiative_winner: self.battle_order_list[0].team.to_string(),
             ..Default::default()
         };
+        for order in self.battle_order_list.iter_mut() {
+            order.character.roll_hit_points(&mut request_cache);
+            // unless already set, as for a wounded party later in the day
+            order.max_hp = order.max_hp.max(order.character.hit_points());
+        }
+        if let Some(battlefield) = self.battlefield.clone() {
+            self.place_combatants(&battlefield);
+        }
+        for i in 0..self.battle_order_list.len() {
+            BattleOrderList::announce(&self.battle_order_list, i, self.rules.ruleset(), &mut self.event_log);
+        }
     
-        while !winning_result {
-            self = self.run_battle_turn(turn_number, &mut request_cache);
-            self.battle_result.turn_result.push(turn_result.clone());
-
-            if self.is_there_a_winner() {
-                winning_result = true;
+        loop {
+            self.event_log.record(|| Event::RoundStarted { round: turn_number });
+            self.call_reinforcements(turn_number, &mut request_cache);
+            let (battle, turn_result) = self.run_battle_turn(turn_number, &mut request_cache);
+            self = battle;
+            if let ReportOutputLevel::Turns = report_level {
+                self.battle_result.turn_result.push(turn_result);
             }
-            else {
-                turn_number += 1;
+
+            if let Some(outcome) = self.outcome(turn_number, max_rounds) {
+                self.battle_result.outcome = outcome;
+                break;
             }
+            turn_number += 1;
         }
 
         let winner = self.get_winner();
@@ -387,97 +760,592 @@ impl BattleOrderList {
         }
 
         self.battle_result.turns_run = turn_number;
-        if let ReportOutputLevel::Summary = report_level {
-            let result_summary = self.battle_result.summarize();
-            if let Some(summary) = result_summary {     
-                println!("{}",summary);           
-            }
-        }
+        let outcome = self.battle_result.outcome;
+        self.event_log.record(|| Event::BattleEnded { rounds: turn_number, outcome: outcome.to_string(), winner: outcome.winner() });
         
         self
     }
     
-    fn run_battle_turn(mut self, turn_number: u8, request_cache: &mut RequestCache) -> Self {
-        let mut action_result: ActionResult;
+    /// Gives every combatant still fighting its turn, returning the battle and
+    /// what happened in the round.
+    fn run_battle_turn(mut self, turn_number: u8, request_cache: &mut RequestCache) -> (Self, TurnResult) {
         let mut turn_order = self.battle_order_list.clone();
         let mut turn_result = TurnResult {
             turn_number,
             ..Default::default()
         }; 
 
-        for i in 0..turn_order.len(){
-            if turn_order[i].character.is_concious() {
-                if let Some(target) = turn_order[i].get_target(&turn_order) {
-                    let a_res = turn_order[i].make_attack(request_cache);
-
-                    if turn_order[target].is_attack_successful(&a_res) {
-                        let d_res = turn_order[i].get_damage(request_cache);
-                        turn_order[target].give_damage(d_res.damage as u16);
-
-                        action_result = ActionResult {
-                            actor: turn_order[i].character.name.clone(),
-                            target: turn_order[target].character.name.clone(),
-                            action_type: ActionType::Attack,
-                            action_roll: a_res.attack_roll,
-                            action_result: ActionResultType::Hit,
-                            action_damage: d_res.damage as u16, 
-                            action_number: i as u16,
-                        };
-                    }
-                    else {
-                        action_result = ActionResult {
-                            actor: turn_order[i].character.name.clone(),
-                            target: turn_order[target].character.name.clone(),
-                            action_type: ActionType::Attack,
-                            action_roll: a_res.attack_roll,
-                            action_result: ActionResultType::Miss,
-                            action_damage: 0, 
-                            action_number: i as u16,
-                        };
+        for (k, order) in turn_order.iter_mut().enumerate().filter(|(_, order)| !order.character.effects.is_empty()) {
+            order.character.update_armour_class(turn_number);
+            let outlined = order.character.outlined(turn_number);
+            self.event_log.condition(k, Condition::Outlined, order.outlined, outlined);
+            order.outlined = outlined;
+        }
+
+        for i in BattleOrderList::turn_sequence(&turn_order) {
+            if turn_order[i].is_fighting() {
+                // a dodge and any help given last only until the creature's next turn
+                self.event_log.condition(i, Condition::Dodging, turn_order[i].dodging, false);
+                self.event_log.condition(i, Condition::Disengaged, turn_order[i].disengaged, false);
+                self.event_log.condition(i, Condition::Reckless, turn_order[i].reckless, false);
+                turn_order[i].dodging = false;
+                turn_order[i].disengaged = false;
+                turn_order[i].reckless = false;
+                turn_order[i].reaction_used = false;
+                for (other, order) in turn_order.iter_mut().enumerate().filter(|(_, other)| other.helped_by == Some(i)) {
+                    self.event_log.condition(other, Condition::Helped, true, false);
+                    order.helped_by = None;
+                }
+
+                let action = match turn_order[i].morale_breaks(&turn_order) {
+                    Some(MoraleResponse::Flee) => Action::Flee,
+                    Some(MoraleResponse::Surrender) => Action::Surrender,
+                    None => {
+                        let view = BattleView { combatants: &turn_order, actor: i, ruleset: self.rules.ruleset() };
+                        self.policies.for_character(&turn_order[i].character).choose_action(&view)
                     }
+                };
+                let mut movement = turn_order[i].character.speed();
+                if turn_order[i].prone {
+                    // standing up costs half a move
+                    self.event_log.condition(i, Condition::Prone, true, false);
+                    turn_order[i].prone = false;
+                    movement -= movement / 2;
                 }
-                else {
-                    action_result = ActionResult {
-                        actor: turn_order[i].character.name.clone(),
-                        target: "no target".to_string(),
-                        action_type: ActionType::_NoTarget,
-                        action_roll: 0,
-                        action_result: ActionResultType::Miss,
-                        action_damage: 0, 
-                        action_number: i as u16,
-                    };
+
+                match action {
+                    Action::Attack(first_target) => {
+                        if turn_order[i].character.attacks_recklessly() {
+                            self.event_log.condition(i, Condition::Reckless, turn_order[i].reckless, true);
+                            turn_order[i].reckless = true;
+                        }
+                        let action_economy = self.rules.ruleset().actions_per_turn();
+                        let mut actions = action_economy.unwrap_or_else(|| turn_order[i].character.actions_per_round.max(1));
+                        let mut target = Some(first_target);
+                        while actions > 0 {
+                            target = target.filter(|target| turn_order[*target].is_fighting())
+                                .or_else(|| turn_order[i].get_target(&turn_order, self.rules.ruleset()));
+                            match target {
+                                Some(target) => {
+                                    let battlefield = self.battlefield.clone();
+                                    let out_of_sight = |turn_order: &[BattleOrder]| turn_order[target].cover_from(turn_order[i].position, battlefield.as_deref()) == Some(Cover::Total);
+                                    if turn_order[i].distance_to(&turn_order[target]).is_some_and(|gap| gap > turn_order[i].character.preferred_range())
+                                        || out_of_sight(&turn_order) {
+                                        // with an action economy each stride is an action, good for a full move
+                                        if action_economy.is_some() {
+                                            actions -= 1;
+                                            movement = turn_order[i].character.speed();
+                                        }
+                                        movement = self.close_in(&mut turn_order, i, target, movement, request_cache, &mut turn_result);
+                                        if actions == 0 || !turn_order[i].is_fighting() || !turn_order[i].in_range(&turn_order[target]) || out_of_sight(&turn_order) {
+                                            break;
+                                        }
+                                    }
+                                    let action_result = BattleOrderList::resolve_attack(&mut turn_order, i, target, battlefield.as_deref(), self.rules.ruleset(), self.flanking, request_cache, &mut self.event_log);
+                                    self.record_attack(&mut turn_result, action_result);
+                                    turn_order[i].attacks_this_turn += 1;
+                                    actions -= 1;
+                                }
+                                None => break,
+                            }
+                        }
+                        turn_order[i].attacks_this_turn = 0;
+                    }
+                    Action::Pass => {
+                        let action_type = if turn_order[i].get_target(&turn_order, self.rules.ruleset()).is_none() { ActionType::_NoTarget } else { ActionType::_NoAction };
+                        turn_result.action_results.push(turn_order[i].non_attack_result(i, action_type, "no target"));
+                    }
+                    Action::Dodge => {
+                        turn_order[i].dodging = true;
+                        self.event_log.condition(i, Condition::Dodging, false, true);
+                        turn_result.action_results.push(turn_order[i].non_attack_result(i, ActionType::Dodge, "no target"));
+                    }
+                    Action::Help(ally) => {
+                        self.event_log.condition(ally, Condition::Helped, turn_order[ally].helped_by.is_some(), true);
+                        turn_order[ally].helped_by = Some(i);
+                        let ally_name = turn_order[ally].character.name.clone();
+                        turn_result.action_results.push(turn_order[i].non_attack_result(i, ActionType::Help, &ally_name));
+                    }
+                    Action::Hide => {
+                        let action_result = BattleOrderList::attempt_hide(&mut turn_order, i, request_cache, &mut self.event_log);
+                        turn_result.action_results.push(action_result);
+                    }
+                    Action::Cast(target) => match turn_order[i].character.usable_area_attack() {
+                        Some(area) => {
+                            let reach = {
+                                let area = &turn_order[i].character.area_attacks[area];
+                                area.shape.reach(area.range)
+                            };
+                            if let (Some(gap), Some(target_square)) = (turn_order[i].distance_to(&turn_order[target]), turn_order[target].position) {
+                                if gap > reach {
+                                    movement = self.walk(&mut turn_order, i, |square| distance(square, target_square) <= reach, movement, request_cache, &mut turn_result);
+                                }
+                            }
+                            if turn_order[i].is_fighting() && turn_order[i].distance_to(&turn_order[target]).is_none_or(|gap| gap <= reach) {
+                                for action_result in self.resolve_area_attack(&mut turn_order, i, area, target, request_cache) {
+                                    self.record_attack(&mut turn_result, action_result);
+                                }
+                            }
+                        }
+                        None => {
+                            let target_name = turn_order[target].character.name.clone();
+                            turn_result.action_results.push(turn_order[i].non_attack_result(i, ActionType::Cast, &target_name));
+                        }
+                    },
+                    Action::Summon => match turn_order[i].character.usable_summon() {
+                        Some(summon) => {
+                            let creatures = turn_order[i].character.summons[summon].creatures.clone();
+                            if let Some(uses) = turn_order[i].character.summons[summon].uses.as_mut() {
+                                *uses = uses.saturating_sub(1);
+                            }
+                            let summon_name = turn_order[i].character.summons[summon].name.clone();
+                            turn_result.action_results.push(turn_order[i].non_attack_result(i, ActionType::Summon, &summon_name));
+                            let near = turn_order[i].position;
+                            let first = turn_order.len();
+                            BattleOrderList::join(&mut turn_order, self.battlefield.as_deref(), &creatures, near, self.rules.ruleset(), request_cache, &mut self.event_log);
+                            for creature in turn_order[first..].iter_mut() {
+                                creature.summoned_by = Some(i);
+                            }
+                        }
+                        None => turn_result.action_results.push(turn_order[i].non_attack_result(i, ActionType::Summon, "no target")),
+                    },
+                    Action::Heal(patient) => match turn_order[i].character.usable_heal(patient == i) {
+                        Some(heal) => {
+                            let range = turn_order[i].character.heals[heal].range;
+                            if let (Some(gap), Some(patient_square)) = (turn_order[i].distance_to(&turn_order[patient]), turn_order[patient].position) {
+                                if gap > range {
+                                    movement = self.walk(&mut turn_order, i, |square| distance(square, patient_square) <= range, movement, request_cache, &mut turn_result);
+                                }
+                            }
+                            if turn_order[i].is_fighting() && turn_order[i].distance_to(&turn_order[patient]).is_none_or(|gap| gap <= range) {
+                                let action_result = BattleOrderList::resolve_heal(&mut turn_order, i, heal, patient, request_cache, &mut self.event_log);
+                                turn_result.action_results.push(action_result);
+                            }
+                        }
+                        None => {
+                            let patient_name = turn_order[patient].character.name.clone();
+                            turn_result.action_results.push(turn_order[i].non_attack_result(i, ActionType::Heal, &patient_name));
+                        }
+                    },
+                    // without a battlefield there is nowhere to move to, so these only cost the turn
+                    Action::Dash => {
+                        turn_result.action_results.push(turn_order[i].non_attack_result(i, ActionType::Dash, "no target"));
+                        if let Some(target) = turn_order[i].get_target(&turn_order, self.rules.ruleset()) {
+                            self.close_in(&mut turn_order, i, target, movement.saturating_mul(2), request_cache, &mut turn_result);
+                        }
+                    }
+                    Action::Disengage => {
+                        turn_order[i].disengaged = true;
+                        self.event_log.condition(i, Condition::Disengaged, false, true);
+                        turn_result.action_results.push(turn_order[i].non_attack_result(i, ActionType::Disengage, "no target"));
+                        self.back_off(&mut turn_order, i, movement, request_cache, &mut turn_result);
+                    }
+                    // a fleeing creature runs for it, risking opportunity attacks on a battlefield
+                    Action::Flee => {
+                        turn_result.action_results.push(turn_order[i].non_attack_result(i, ActionType::Flee, "no target"));
+                        self.back_off(&mut turn_order, i, movement, request_cache, &mut turn_result);
+                        if turn_order[i].character.is_concious() {
+                            turn_order[i].withdrawn = Some(MoraleResponse::Flee);
+                            self.event_log.condition(i, Condition::Fled, false, true);
+                        }
+                    }
+                    Action::Surrender => {
+                        turn_order[i].withdrawn = Some(MoraleResponse::Surrender);
+                        self.event_log.condition(i, Condition::Surrendered, false, true);
+                        turn_result.action_results.push(turn_order[i].non_attack_result(i, ActionType::Surrender, "no target"));
+                    }
                 }
-                turn_result.action_results.push(action_result);
             }
         }
     self.battle_order_list = turn_order;
-    self
+    (self, turn_result)
 }
 
-    fn is_there_a_winner(&self) -> bool {
-        let mut alive_team = None;
+    fn record_attack(&mut self, turn_result: &mut TurnResult, action_result: ActionResult) {
+        match action_result.action_result {
+            ActionResultType::CritHit => self.battle_result.crit_hits += 1,
+            ActionResultType::CritMiss => self.battle_result.crit_misses += 1,
+            _ => (),
+        }
+        // an attack into total cover is never made, so isn't a covered attack
+        if action_result.cover.is_some() && action_result.action_result != ActionResultType::NoRoll {
+            self.battle_result.covered_attacks += 1;
+        }
+        turn_result.action_results.push(action_result);
+    }
+
+    fn place_combatants(&mut self, battlefield: &Battlefield) {
+        for i in 0..self.battle_order_list.len() {
+            BattleOrderList::place(battlefield, &mut self.battle_order_list, i, None);
+        }
+    }
 
-         for player in &self.battle_order_list {
-            if player.character.is_concious() {
-                match alive_team {
-                    Some(team) => if team != player.team { return false },
-                    None => alive_team = Some(player.team),
-                };
+    /// Puts combatant `i` on the free square nearest `near`, else its starting
+    /// square. Anyone without a `position` starts on their team's edge of the
+    /// field, heroes on the left and villains on the right.
+    fn place(battlefield: &Battlefield, combatants: &mut [BattleOrder], i: usize, near: Option<Position>) {
+        let occupied: HashSet<Position> = combatants.iter().enumerate()
+            .filter(|(other, order)| *other != i && order.is_fighting())
+            .filter_map(|(_, order)| order.position)
+            .collect();
+        let order = &mut combatants[i];
+        let wanted = near.or(order.character.position).unwrap_or(match order.team {
+            Team::Heros => (0, battlefield.height / 2),
+            Team::Villains => (battlefield.width.saturating_sub(1), battlefield.height / 2),
+        });
+        order.position = battlefield.nearest_open(wanted, &occupied);
+    }
+
+    /// Combatants by initiative. Anyone who joined part way through acts on
+    /// their own roll from the next round, after those already tied with it.
+    fn turn_sequence(turn_order: &[BattleOrder]) -> Vec<usize> {
+        let mut sequence: Vec<usize> = (0..turn_order.len()).collect();
+        sequence.sort_by_key(|i| std::cmp::Reverse((turn_order[*i].initative_roll, turn_order[*i].character.dexterity_modifier)));
+        sequence
+    }
+
+    /// Adds `characters` to the end of the battle order, so existing targets
+    /// keep their indexes, with their own initiative and hit points.
+    fn join(turn_order: &mut Vec<BattleOrder>, battlefield: Option<&Battlefield>, characters: &[CharacterStruct], near: Option<Position>, ruleset: &dyn Ruleset, request_cache: &mut RequestCache, event_log: &mut EventLog) {
+        for character in characters {
+            let initiative = ruleset.initiative(character, turn_order, request_cache);
+            let mut order = enter_battle(character, initiative);
+            order.character.roll_hit_points(request_cache);
+            order.max_hp = order.character.hit_points();
+            turn_order.push(order);
+            let newcomer = turn_order.len() - 1;
+            if let Some(battlefield) = battlefield {
+                BattleOrderList::place(battlefield, turn_order, newcomer, near);
             }
-         }
-        true
+            BattleOrderList::announce(turn_order, newcomer, ruleset, event_log);
+        }
     }
 
-    fn get_winner(&self) -> Option<&BattleOrder> {
-        for player in &self.battle_order_list {
-            if player.character.is_concious() {
-                return Some(player);
+    fn announce(turn_order: &[BattleOrder], i: usize, ruleset: &dyn Ruleset, event_log: &mut EventLog) {
+        let order = &turn_order[i];
+        event_log.record(|| Event::Joined {
+            combatant: i,
+            name: order.character.name.clone(),
+            team: order.team,
+            health: order.character.hs2,
+            position: order.position,
+        });
+        let modifier = ruleset.initiative_modifier(&order.character);
+        event_log.record(|| Event::InitiativeRolled {
+            combatant: i,
+            roll: (order.initative_roll - modifier) as u8,
+            modifier,
+            initiative: order.initative_roll,
+        });
+    }
+
+    /// Brings in every reinforcement whose trigger has fired by the start of `turn_number`.
+    fn call_reinforcements(&mut self, turn_number: u8, request_cache: &mut RequestCache) {
+        if self.reinforcements.is_empty() {
+            return;
+        }
+        let (arrived, pending): (Vec<Reinforcement>, Vec<Reinforcement>) = std::mem::take(&mut self.reinforcements)
+            .into_iter()
+            .partition(|reinforcement| self.is_triggered(reinforcement, turn_number));
+        self.reinforcements = pending;
+        for reinforcement in arrived {
+            BattleOrderList::join(&mut self.battle_order_list, self.battlefield.as_deref(), &reinforcement.characters, None, self.rules.ruleset(), request_cache, &mut self.event_log);
+        }
+    }
+
+    fn is_triggered(&self, reinforcement: &Reinforcement, turn_number: u8) -> bool {
+        match reinforcement.trigger {
+            Trigger::Round { round } => turn_number >= round,
+            Trigger::LeaderBelow { percent } => {
+                let team = reinforcement.characters.first().map(|character| character.team);
+                self.battle_order_list.iter()
+                    .filter(|order| Some(order.team) == team && order.character.leader)
+                    .any(|leader| (leader.character.hit_points() as u32) * 100 < leader.max_hp as u32 * percent as u32)
             }
         }
-        println!("get_winner but none found in {:?}",self.battle_order_list);
+    }
+
+    /// Moves `mover` toward where it can attack `target` without penalty and
+    /// from behind no total cover, returning the movement it has left.
+    fn close_in(&mut self, turn_order: &mut [BattleOrder], mover: usize, target: usize, movement: u8, request_cache: &mut RequestCache, turn_result: &mut TurnResult) -> u8 {
+        let (target_square, battlefield) = match (turn_order[target].position, &self.battlefield) {
+            (Some(square), Some(battlefield)) => (square, battlefield.clone()),
+            _ => return movement,
+        };
+        let preferred_range = turn_order[mover].character.preferred_range();
+        self.walk(turn_order, mover, |square| distance(square, target_square) <= preferred_range
+            && battlefield.cover(square, target_square) != Some(Cover::Total), movement, request_cache, turn_result)
+    }
+
+    /// Moves `mover` to the nearest square out of reach of every enemy that
+    /// fights in melee, returning the movement it has left.
+    fn back_off(&mut self, turn_order: &mut [BattleOrder], mover: usize, movement: u8, request_cache: &mut RequestCache, turn_result: &mut TurnResult) -> u8 {
+        let team = turn_order[mover].team;
+        let threats: Vec<(Position, u8)> = turn_order.iter()
+            .filter(|other| other.team != team && other.is_fighting() && other.character.range.is_none())
+            .filter_map(|other| other.position.map(|square| (square, other.character.attack_range())))
+            .collect();
+        self.walk(turn_order, mover, |square| threats.iter().all(|(threat, reach)| distance(square, *threat) > *reach), movement, request_cache, turn_result)
+    }
+
+    /// Walks `mover` up to `movement` squares along the shortest path to a
+    /// square meeting `goal`. Each step out of an enemy's reach provokes an
+    /// opportunity attack unless the mover disengaged.
+    fn walk(&mut self, turn_order: &mut [BattleOrder], mover: usize, goal: impl Fn(Position) -> bool, movement: u8, request_cache: &mut RequestCache, turn_result: &mut TurnResult) -> u8 {
+        let battlefield = match (&self.battlefield, turn_order[mover].position) {
+            (Some(battlefield), Some(_)) => battlefield.clone(),
+            _ => return movement,
+        };
+        let occupied: HashSet<Position> = turn_order.iter().enumerate()
+            .filter(|(other, order)| *other != mover && order.is_fighting())
+            .filter_map(|(_, order)| order.position)
+            .collect();
+        let path = match battlefield.path_to(turn_order[mover].position.unwrap(), &occupied, goal) {
+            Some(path) => path,
+            None => return movement,
+        };
+
+        let mut movement = movement;
+        for square in path.into_iter().take(movement as usize) {
+            let from = turn_order[mover].position.unwrap();
+            turn_order[mover].position = Some(square);
+            self.event_log.record(|| Event::Moved { combatant: mover, from, to: square });
+            movement -= 1;
+            if turn_order[mover].disengaged {
+                continue;
+            }
+            for enemy in 0..turn_order.len() {
+                // with nothing to swing at, the reaction isn't spent
+                if turn_order[enemy].provoked_by(&turn_order[mover], from) && turn_order[mover].is_fighting()
+                    && self.rules.ruleset().opportunity_attacks(&turn_order[enemy].character)
+                    && turn_order[mover].cover_from(turn_order[enemy].position, Some(&battlefield)) != Some(Cover::Total) {
+                    turn_order[enemy].reaction_used = true;
+                    let action_result = BattleOrderList::resolve_attack(turn_order, enemy, mover, Some(&battlefield), self.rules.ruleset(), self.flanking, request_cache, &mut self.event_log);
+                    self.record_attack(turn_result, ActionResult { action_type: ActionType::OpportunityAttack, ..action_result });
+                }
+            }
+            if !turn_order[mover].is_fighting() {
+                break;
+            }
+        }
+        movement
+    }
+
+    /// Stealth against the sharpest enemy's passive perception; success hides
+    /// the creature until it next attacks.
+    fn attempt_hide(turn_order: &mut [BattleOrder], hider: usize, request_cache: &mut RequestCache, event_log: &mut EventLog) -> ActionResult {
+        let request = request_cache.get_roll_request("d20");
+        let check = (dice_thrower::throw_roll(&request) as i16 + turn_order[hider].character.dexterity_modifier as i16).max(0) as u8;
+        let team = turn_order[hider].team;
+        let perception = turn_order.iter()
+            .filter(|other| other.team != team && other.is_fighting())
+            .map(|other| other.character.passive_perception())
+            .max()
+            .unwrap_or(0);
+        event_log.condition(hider, Condition::Hidden, turn_order[hider].hidden, check >= perception);
+        turn_order[hider].hidden = check >= perception;
+
+        ActionResult {
+            action_roll: check,
+            action_result: if turn_order[hider].hidden { ActionResultType::Hit } else { ActionResultType::Miss },
+            ..turn_order[hider].non_attack_result(hider, ActionType::Hide, "no target")
+        }
+    }
+
+    /// Rolls `attacker`'s attack on `target`, whose armour class counts any
+    /// cover it has from the attacker. There's no attack at all on a target
+    /// in total cover. `flanking` puts the optional flanking rule in play.
+    #[allow(clippy::too_many_arguments)]
+    fn resolve_attack(turn_order: &mut [BattleOrder], attacker: usize, target: usize, battlefield: Option<&Battlefield>, ruleset: &dyn Ruleset, flanking: bool, request_cache: &mut RequestCache, event_log: &mut EventLog) -> ActionResult {
+        let cover = turn_order[target].cover_from(turn_order[attacker].position, battlefield);
+        if cover == Some(Cover::Total) {
+            let target_name = turn_order[target].character.name.clone();
+            return ActionResult { cover, ..turn_order[attacker].non_attack_result(attacker, ActionType::Attack, &target_name) };
+        }
+        let armour_class = turn_order[target].character.armour_class.saturating_add(cover.map_or(0, Cover::bonus));
+        turn_order[attacker].current_target = Some(target);
+        let sources = turn_order[attacker].advantage_sources(target, turn_order, flanking);
+        let advantage = RollMode::resolve(&sources);
+        // attacking gives away a hiding place and uses up any help
+        event_log.condition(attacker, Condition::Hidden, turn_order[attacker].hidden, false);
+        event_log.condition(attacker, Condition::Helped, turn_order[attacker].helped_by.is_some(), false);
+        turn_order[attacker].hidden = false;
+        turn_order[attacker].helped_by = None;
+        let mut a_res = turn_order[attacker].make_attack(request_cache, advantage);
+        let penalty = ruleset.attack_penalty(turn_order[attacker].attacks_this_turn);
+        a_res.attack_roll = a_res.attack_roll.saturating_sub(penalty);
+        let outcome = ruleset.attack_outcome(armour_class, &a_res);
+        let hit = matches!(outcome, ActionResultType::Hit | ActionResultType::CritHit);
+        event_log.record(|| Event::AttackRolled {
+            attacker,
+            target,
+            rolls: std::iter::once(a_res.natural_roll).chain(a_res.other_roll).collect(),
+            kept: a_res.natural_roll,
+            bonus: turn_order[attacker].character.proficiency_bonus,
+            penalty,
+            total: a_res.attack_roll,
+            armour_class,
+            critical: outcome == ActionResultType::CritHit,
+            hit,
+            sources,
+            cover,
+            // an opportunity attack spends the reaction before it's rolled
+            opportunity: turn_order[attacker].reaction_used,
+        });
+
+        if hit {
+            let d_res = turn_order[attacker].get_damage(ruleset, request_cache, outcome == ActionResultType::CritHit);
+            BattleOrderList::apply_damage(turn_order, target, d_res.damage, ruleset, event_log);
+
+            ActionResult {
+                actor: turn_order[attacker].character.name.clone(),
+                target: turn_order[target].character.name.clone(),
+                action_type: ActionType::Attack,
+                action_roll: a_res.attack_roll,
+                action_result: outcome,
+                action_damage: d_res.damage, 
+                action_number: attacker as u16,
+                cover,
+            }
+        }
+        else {
+            ActionResult {
+                actor: turn_order[attacker].character.name.clone(),
+                target: turn_order[target].character.name.clone(),
+                action_type: ActionType::Attack,
+                action_roll: a_res.attack_roll,
+                action_result: outcome,
+                action_damage: 0, 
+                action_number: attacker as u16,
+                cover,
+            }
+        }
+    }
+
+    /// Rolls the caster's area attack damage once, then has everyone caught
+    /// in it save for half, giving one result per creature. Cover is reckoned
+    /// from where a sphere bursts, otherwise from the caster: total cover
+    /// keeps a creature out of it and lesser cover helps Dexterity saves.
+    fn resolve_area_attack(&mut self, turn_order: &mut [BattleOrder], caster: usize, area: usize, aim: usize, request_cache: &mut RequestCache) -> Vec<ActionResult> {
+        let (battlefield, ruleset, event_log) = (self.battlefield.as_deref(), self.rules.ruleset(), &mut self.event_log);
+        let attack = turn_order[caster].character.area_attacks[area].clone();
+        if let Some(uses) = turn_order[caster].character.area_attacks[area].uses.as_mut() {
+            *uses = uses.saturating_sub(1);
+        }
+        turn_order[caster].current_target = Some(aim);
+        event_log.condition(caster, Condition::Hidden, turn_order[caster].hidden, false);
+        turn_order[caster].hidden = false;
+        let origin = match attack.shape {
+            AreaShape::Sphere { .. } => turn_order[aim].position,
+            _ => turn_order[caster].position,
+        };
+        let targets: Vec<(usize, Option<Cover>)> = turn_order[caster].area_targets(&attack, aim, turn_order).into_iter()
+            .map(|target| (target, turn_order[target].cover_from(origin, battlefield)))
+            .filter(|(_, cover)| *cover != Some(Cover::Total))
+            .collect();
+        let damage = dice_thrower::throw_roll(&request_cache.get_roll_request(&attack.damage));
+        let save_die = request_cache.get_roll_request("d20");
+        event_log.record(|| Event::AreaAttack { attacker: caster, name: attack.name.clone(), damage });
+
+        targets.into_iter().map(|(target, cover)| {
+            let roll = dice_thrower::throw_roll(&save_die) as u8;
+            let cover = cover.filter(|_| attack.save == SaveAbility::Dexterity);
+            let modifier = turn_order[target].character.saving_throw(attack.save) + cover.map_or(0, Cover::bonus) as i8;
+            let save = (roll as i16 + modifier as i16).max(0) as u8;
+            let saved = save >= attack.save_dc;
+            event_log.record(|| Event::SavingThrow { combatant: target, roll, modifier, total: save, dc: attack.save_dc, saved, cover });
+            let taken = if !saved { damage } else if attack.half_on_save { damage / 2 } else { 0 };
+            BattleOrderList::apply_damage(turn_order, target, taken, ruleset, event_log);
+
+            ActionResult {
+                action_roll: save,
+                action_result: if saved { ActionResultType::Miss } else { ActionResultType::Hit },
+                action_damage: taken,
+                cover,
+                ..turn_order[caster].non_attack_result(caster, ActionType::Cast, &turn_order[target].character.name)
+            }
+        }).collect()
+    }
+
+    /// Spends a use of the healer's `heal`, or as much of its pool as the
+    /// patient needs, restoring hit points up to the patient's starting ones.
+    fn resolve_heal(turn_order: &mut [BattleOrder], healer: usize, heal: usize, patient: usize, request_cache: &mut RequestCache, event_log: &mut EventLog) -> ActionResult {
+        let missing = turn_order[patient].max_hp.saturating_sub(turn_order[patient].character.hit_points());
+        let heal = &mut turn_order[healer].character.heals[heal];
+        let amount = match (heal.pool.as_mut(), &heal.healing) {
+            (Some(pool), _) => {
+                let spent = (*pool).min(missing);
+                *pool -= spent;
+                spent
+            }
+            (None, Some(healing)) => dice_thrower::throw_roll(&request_cache.get_roll_request(healing)),
+            (None, None) => 0,
+        };
+        if let Some(uses) = heal.uses.as_mut() {
+            *uses = uses.saturating_sub(1);
+        }
+        let name = heal.name.clone();
+
+        let before = turn_order[patient].character.hs2;
+        let healed = turn_order[patient].heal(amount);
+        let after = turn_order[patient].character.hs2;
+        event_log.record(|| Event::Healed { healer, target: patient, name, amount: healed, health: after });
+        if std::mem::discriminant(&before) != std::mem::discriminant(&after) {
+            event_log.record(|| Event::StateChanged { combatant: patient, from: before, to: after });
+        }
+
+        ActionResult {
+            action_roll: healed.min(u8::MAX as u16) as u8,
+            ..turn_order[healer].non_attack_result(healer, ActionType::Heal, &turn_order[patient].character.name)
+        }
+    }
+
+    fn apply_damage(turn_order: &mut [BattleOrder], target: usize, damage: u16, ruleset: &dyn Ruleset, event_log: &mut EventLog) {
+        let before = turn_order[target].character.hs2;
+        turn_order[target].give_damage(damage, ruleset);
+        let after = turn_order[target].character.hs2;
+        event_log.record(|| Event::DamageApplied { target, amount: damage, health: after });
+        if std::mem::discriminant(&before) != std::mem::discriminant(&after) {
+            event_log.record(|| Event::StateChanged { combatant: target, from: before, to: after });
+        }
+        if after == HealthState::Ko {
+            // the unconscious fall prone, and stay so until they get up
+            event_log.condition(target, Condition::Prone, turn_order[target].prone, true);
+            turn_order[target].prone = true;
+        }
+    }
+
+    /// How the battle ended after `turn_number`, or None if it goes on.
+    fn outcome(&self, turn_number: u8, max_rounds: u8) -> Option<BattleOutcome> {
+        if let Some(outcome) = self.rules.ruleset().victory(&self.battle_order_list) {
+            return Some(outcome);
+        }
+        if self.is_stalemate() {
+            return Some(BattleOutcome::Stalemate);
+        }
+        if turn_number >= max_rounds {
+            return Some(BattleOutcome::Timeout);
+        }
         None
     }
 
+    /// True when nobody left standing can ever hurt an enemy, so fighting on
+    /// would only run into the round limit. Not while reinforcements are
+    /// still to come or anyone can still summon, as they may break it.
+    fn is_stalemate(&self) -> bool {
+        let standing = || self.battle_order_list.iter().filter(|order| order.is_fighting());
+        if !self.reinforcements.is_empty() || standing().any(|order| order.character.usable_summon().is_some()) {
+            return false;
+        }
+        let ruleset = self.rules.ruleset();
+        !standing().any(|attacker| standing().any(|target| target.team != attacker.team && attacker.can_ever_hurt(target, ruleset)))
+    }
+
+    fn get_winner(&self) -> Option<&BattleOrder> {
+        // None when everyone went down together
+        self.battle_order_list.iter().find(|player| player.is_fighting())
+    }
+
     fn _get_initiative_winner(&self) -> CharacterStruct {
         self.battle_order_list[0].character.clone()
     }
@@ -485,44 +1353,267 @@ impl BattleOrderList {
 
 #[derive(Default, Debug, Eq, Ord, PartialEq, PartialOrd, Clone)]
 struct BattleOrder {
-    initative_roll: u8,
+    initative_roll: i8,
     character: CharacterStruct,
     team: Team,
+    current_target: Option<usize>,
+    dodging: bool,
+    hidden: bool,
+    helped_by: Option<usize>,
+    position: Option<Position>,
+    disengaged: bool,
+    reaction_used: bool,
+    max_hp: u16,
+    /// Fled or surrendered, and so out of the fight.
+    withdrawn: Option<MoraleResponse>,
+    /// Attacks made on its own turn, for the multiple attack penalty.
+    attacks_this_turn: u8,
+    prone: bool,
+    /// Attacked recklessly this turn, so attacks against it have advantage
+    /// until its next.
+    reckless: bool,
+    outlined: bool,
+    /// Where it came in the characters the battle was made from; None for
+    /// reinforcements and summons.
+    roster_index: Option<usize>,
+    /// Who called it up, for summoned creatures.
+    summoned_by: Option<usize>,
 }
 
 impl BattleOrder {
-    fn make_attack(&self, request_cache: &mut RequestCache) -> AttackResult {
-        let request = request_cache.get_roll_request(
-            &format!("d{}", self.character.to_hit));       // lexer needs a fix, till then prepend d
-            
-        
+    fn make_attack(&self, request_cache: &mut RequestCache, advantage: RollMode) -> AttackResult {
+        let roll_string = format!("d{}", self.character.to_hit);       // lexer needs a fix, till then prepend d
+        let request = request_cache.get_roll_request(&roll_string);
+        let first = dice_thrower::throw_roll(&request) as u8;
+        let (natural_roll, other_roll) = match advantage {
+            RollMode::Normal => (first, None),
+            RollMode::Advantage | RollMode::Disadvantage => {
+                let second = dice_thrower::throw_roll(&request) as u8;
+                let (high, low) = (first.max(second), first.min(second));
+                if advantage == RollMode::Advantage { (high, Some(low)) } else { (low, Some(high)) }
+            }
+        };
+
         AttackResult {
-            attack_roll: dice_thrower::throw_roll(&request) as u8,
-            _roll_string: format!("d{}", self.character.to_hit).to_string(),
+            attack_roll: natural_roll + self.character.proficiency_bonus,
+            natural_roll,
+            other_roll,
+            critical: natural_roll >= self.character.crit_threshold(),
+            _roll_string: roll_string,
         }
     }
 
-    fn get_target(&self, target_list: &[BattleOrder]) -> Option<usize> {
-        self.character.select_target(target_list)
+    fn get_target(&self, target_list: &[BattleOrder], ruleset: &dyn Ruleset) -> Option<usize> {
+        self.character.targeting.strategy().select_target(self, target_list, ruleset)
+    }
+
+    /// Conscious and neither fled nor surrendered.
+    fn is_fighting(&self) -> bool {
+        self.character.is_concious() && self.withdrawn.is_none()
     }
 
-    fn is_attack_successful(&self, attack_result: &AttackResult) -> bool {
-        self.character.is_attack_successful(&attack_result)
+    /// How this creature gives up, if its morale has broken.
+    fn morale_breaks(&self, combatants: &[BattleOrder]) -> Option<MoraleResponse> {
+        let morale = self.character.morale.as_ref()?;
+        let remaining = |order: &BattleOrder| if order.is_fighting() { order.character.hit_points() as u32 } else { 0 };
+        let below = |current: u32, starting: u32, percent: Option<u8>| percent.is_some_and(|percent| current * 100 < starting * percent as u32);
+        let team: Vec<&BattleOrder> = combatants.iter().filter(|order| order.team == self.team).collect();
+
+        let broken = below(remaining(self), self.max_hp as u32, morale.below_percent)
+            || below(team.iter().map(|order| remaining(order)).sum(), team.iter().map(|order| order.max_hp as u32).sum(), morale.team_below_percent)
+            || (morale.leader_loss && team.iter().any(|order| order.character.leader)
+                && !team.iter().any(|order| order.character.leader && order.is_fighting()));
+        if broken { Some(morale.response) } else { None }
     }
 
-    fn get_damage(&self, request_cache: &mut RequestCache) -> DamageResult {
-        let request = request_cache.get_roll_request(
-            &format!("d{}", self.character.damage));       // lexer needs a fix, till then prepend d
-            
+    /// Squares between the two, or None when fighting without a battlefield.
+    fn distance_to(&self, other: &BattleOrder) -> Option<u8> {
+        Some(distance(self.position?, other.position?))
+    }
+
+    fn in_range(&self, target: &BattleOrder) -> bool {
+        self.distance_to(target).is_none_or(|gap| gap <= self.character.attack_range())
+    }
+
+    /// Close enough to attack `target` after a full move, ignoring obstacles.
+    fn within_reach_this_turn(&self, target: &BattleOrder) -> bool {
+        self.distance_to(target).is_none_or(|gap| gap <= self.character.speed().saturating_add(self.character.attack_range()))
+    }
+
+    /// Everything giving this creature's attack on `combatants[target]`
+    /// advantage or disadvantage, whether or not they cancel out. `flanking`
+    /// puts the optional flanking rule in play.
+    fn advantage_sources(&self, target: usize, combatants: &[BattleOrder], flanking: bool) -> Vec<AdvantageSource> {
+        let allies: Vec<&BattleOrder> = combatants.iter()
+            .filter(|ally| ally.team == self.team && !std::ptr::eq(*ally, self) && ally.is_fighting() && ally.engaging(target, combatants))
+            .collect();
+        let target = &combatants[target];
+        let close = self.distance_to(target).is_none_or(|gap| gap <= 1);
+        let flanked = self.character.range.is_none() && close
+            && allies.iter().any(|ally| ally.character.range.is_none() && ally.opposite(self, target));
+        let sources = [
+            (self.hidden, AdvantageSource::AttackerHidden),
+            (self.helped_by.is_some(), AdvantageSource::Helped),
+            // only on its own turn, and an opportunity attack spends the reaction first
+            (self.reckless && self.character.attacks_recklessly() && !self.reaction_used, AdvantageSource::RecklessAttack),
+            (target.reckless, AdvantageSource::TargetReckless),
+            (target.prone && close, AdvantageSource::TargetProne),
+            (target.outlined, AdvantageSource::FaerieFire),
+            (self.character.has_pack_tactics() && !allies.is_empty(), AdvantageSource::PackTactics),
+            (flanking && flanked, AdvantageSource::Flanking),
+            (target.dodging, AdvantageSource::TargetDodging),
+            (target.hidden, AdvantageSource::TargetHidden),
+            (target.prone && !close, AdvantageSource::TargetProneAtRange),
+            (self.prone, AdvantageSource::AttackerProne),
+        ];
+        sources.iter()
+            .filter(|(applies, _)| *applies)
+            .map(|(_, source)| *source)
+            .chain(self.ranged_disadvantage(target, combatants))
+            .collect()
+    }
+
+    /// Next to `combatants[target]`, or without a battlefield, going after it too.
+    fn engaging(&self, target: usize, combatants: &[BattleOrder]) -> bool {
+        match self.distance_to(&combatants[target]) {
+            Some(gap) => gap <= 1,
+            None => self.current_target == Some(target),
+        }
+    }
+
+    /// Standing on the far side of `target` from `other`, so the line between
+    /// them runs through opposite sides or corners of its square. Anyone
+    /// engaging it counts without a battlefield.
+    fn opposite(&self, other: &BattleOrder, target: &BattleOrder) -> bool {
+        match (self.position, other.position, target.position) {
+            (Some(square), Some(other), Some(target)) =>
+                square.0 as i16 + other.0 as i16 == 2 * target.0 as i16 && square.1 as i16 + other.1 as i16 == 2 * target.1 as i16,
+            _ => true,
+        }
+    }
+
+    /// Ranged attacks beyond normal range, or with an enemy standing next to
+    /// the attacker, are made at disadvantage.
+    fn ranged_disadvantage(&self, target: &BattleOrder, combatants: &[BattleOrder]) -> Option<AdvantageSource> {
+        let range = self.character.range?;
+        let threatened = combatants.iter()
+            .any(|other| other.team != self.team && other.is_fighting() && self.distance_to(other).is_some_and(|gap| gap <= 1));
+        if threatened { Some(AdvantageSource::Threatened) }
+        else if self.distance_to(target).is_some_and(|gap| gap > range.normal) { Some(AdvantageSource::LongRange) }
+        else { None }
+    }
+
+    /// Cover this creature has against anything coming from `origin`: its
+    /// own, or whatever the battlefield's obstacles give, whichever is better.
+    fn cover_from(&self, origin: Option<Position>, battlefield: Option<&Battlefield>) -> Option<Cover> {
+        let obstacles = match (battlefield, origin, self.position) {
+            (Some(battlefield), Some(origin), Some(square)) => battlefield.cover(origin, square),
+            _ => None,
+        };
+        self.character.cover.max(obstacles)
+    }
+
+    /// False only when no roll could ever damage `target`: no face of the die
+    /// hits under `ruleset`, the weapon does no damage and there are no area
+    /// attacks left, or it always has total cover. Armour class that may drop
+    /// when an effect ends counts as hittable.
+    fn can_ever_hurt(&self, target: &BattleOrder, ruleset: &dyn Ruleset) -> bool {
+        if target.character.cover == Some(Cover::Total) {
+            return false;
+        }
+        let lands = self.hit_chance(&target.character, ruleset) > 0.0
+            || target.character.effects.iter().any(|effect| effect.duration.is_some());
+        (lands && self.character.damage > 0) || self.character.usable_area_attack().is_some()
+    }
+
+    /// Everyone `area` aimed at `aim` catches: friend or foe inside the shape
+    /// on a battlefield, otherwise `aim` and the enemies after it in battle
+    /// order, as many as the shape would typically hold.
+    fn area_targets(&self, area: &AreaAttack, aim: usize, combatants: &[BattleOrder]) -> Vec<usize> {
+        if let (Some(origin), Some(aim_square)) = (self.position, combatants[aim].position) {
+            return combatants.iter().enumerate()
+                .filter(|(_, other)| other.is_fighting()
+                    && other.position.is_some_and(|square| area.shape.covers(origin, aim_square, square)))
+                .map(|(i, _)| i)
+                .collect();
+        }
+        let mut targets = vec![aim];
+        targets.extend(combatants.iter().enumerate()
+            .filter(|(i, other)| *i != aim && other.team != self.team && other.is_fighting())
+            .map(|(i, _)| i)
+            .take(area.shape.targets_without_grid() as usize - 1));
+        targets
+    }
+
+    /// Whether `mover` stepping off `from` leaves this creature's melee reach.
+    fn provoked_by(&self, mover: &BattleOrder, from: Position) -> bool {
+        if self.team == mover.team || self.reaction_used || self.character.range.is_some() || !self.is_fighting() {
+            return false;
+        }
+        match (self.position, mover.position) {
+            (Some(square), Some(to)) => {
+                let reach = self.character.attack_range();
+                distance(square, from) <= reach && distance(square, to) > reach
+            }
+            _ => false,
+        }
+    }
+
+    /// Odds of one attack hitting `target` under `ruleset`, crits and
+    /// natural 1s included.
+    fn hit_chance(&self, target: &CharacterStruct, ruleset: &dyn Ruleset) -> f32 {
+        let die = self.character.to_hit.max(1);
+        let hits = (1..=die)
+            .filter(|face| matches!(ruleset.attack_outcome(target.armour_class, &AttackResult {
+                attack_roll: face + self.character.proficiency_bonus,
+                natural_roll: *face,
+                other_roll: None,
+                critical: *face >= self.character.crit_threshold(),
+                _roll_string: String::new(),
+            }), ActionResultType::Hit | ActionResultType::CritHit))
+            .count();
+        hits as f32 / die as f32
+    }
+
+    fn non_attack_result(&self, action_number: usize, action_type: ActionType, target: &str) -> ActionResult {
+        ActionResult {
+            actor: self.character.name.clone(),
+            target: target.to_string(),
+            action_type,
+            action_roll: 0,
+            action_result: ActionResultType::NoRoll,
+            action_damage: 0,
+            action_number: action_number as u16,
+            cover: None,
+        }
+    }
+
+    fn get_damage(&self, ruleset: &dyn Ruleset, request_cache: &mut RequestCache, critical: bool) -> DamageResult {
         DamageResult {
-            damage: dice_thrower::throw_roll(&request) as u8,
+            damage: ruleset.damage(&self.character, critical, request_cache),
+        }
+    }
+
+    /// Restores up to `amount` hit points, never past the starting ones,
+    /// returning how many it did. Someone knocked out is back up; the dead
+    /// stay dead.
+    fn heal(&mut self, amount: u16) -> u16 {
+        let hit_points = match self.character.hs2 {
+            HealthState::Alive(hit_points) => hit_points,
+            HealthState::Ko => 0,
+            HealthState::Dead => return 0,
+        };
+        let healed = amount.min(self.max_hp.saturating_sub(hit_points));
+        if healed > 0 {
+            self.character.hs2 = HealthState::Alive(hit_points + healed);
         }
+        healed
     }
 
-    fn give_damage(&mut self, damage: u16) -> DamageResult {
-        self.character = self.character.clone().take_damage(damage);
+    fn give_damage(&mut self, damage: u16, ruleset: &dyn Ruleset) -> DamageResult {
+        self.character = self.character.clone().take_damage(damage, ruleset);
         DamageResult {
-            damage: damage as u8,
+            damage,
         }
     }
     
@@ -541,7 +1632,7 @@ struct BattleResultCollection {
     arena_id: u8,
     battle_count: u32,
     battle_result_list: Vec<BattleResult>,
-    battle_order_list: Vec<BattleOrder>,
+    targeting: String,
 }
 
 struct CollectionSummary {
@@ -550,16 +1641,24 @@ struct CollectionSummary {
     total_turns_run: u32,
     average_turns_run: u16,
     max_turns_run: u8,    
+    crit_hits: u32,
+    crit_misses: u32,
+    draws: u32,
+    stalemates: u32,
+    timeouts: u32,
+    routs: u32,
+    covered_attacks: u32,
+    targeting: String,
 }
 
 impl fmt::Display for CollectionSummary {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{},{},{},{},{}", self.arena_id, self.battle_count, self.total_turns_run, self.average_turns_run, self.max_turns_run)
+        write!(f, "{},{},{},{},{},{},{},{},{},{},{},{},{}", self.arena_id, self.battle_count, self.total_turns_run, self.average_turns_run, self.max_turns_run,
+            self.crit_hits, self.crit_misses, self.draws, self.stalemates, self.timeouts, self.routs, self.covered_attacks, self.targeting)
     }
 }
 
 struct CollectionAccumulation {
-    number_of_battles: u32,
     accumulation: HashMap<(u16,String),u32>,
 }
 
@@ -574,6 +1673,12 @@ impl fmt::Display for CollectionAccumulation {
     }    
 }
 
+impl BattleResultCollection {
+    fn count_outcomes(&self, outcome: BattleOutcome) -> u32 {
+        self.battle_result_list.iter().filter(|battle_result| battle_result.outcome == outcome).count() as u32
+    }
+}
+
 impl Summary<CollectionSummary> for BattleResultCollection {
     fn summarize(&self) -> Option<CollectionSummary> {
         let total_turns_run: u32 = self.battle_result_list.iter()
@@ -583,8 +1688,15 @@ impl Summary<CollectionSummary> for BattleResultCollection {
             battle_count: self.battle_count,
             total_turns_run,
             average_turns_run: (total_turns_run /self.battle_count) as u16,
-            max_turns_run: self.battle_result_list.iter().fold(0_u8, |max, battle_result| if max > battle_result.turn_result.len() as u8 { max }
-                else { battle_result.turn_result.len() as u8 }),    
+            max_turns_run: self.battle_result_list.iter().map(|battle_result| battle_result.turns_run).max().unwrap_or(0),
+            crit_hits: self.battle_result_list.iter().map(|battle_result| battle_result.crit_hits as u32).sum(),
+            crit_misses: self.battle_result_list.iter().map(|battle_result| battle_result.crit_misses as u32).sum(),
+            draws: self.count_outcomes(BattleOutcome::Draw),
+            stalemates: self.count_outcomes(BattleOutcome::Stalemate),
+            timeouts: self.count_outcomes(BattleOutcome::Timeout),
+            routs: self.battle_result_list.iter().filter(|battle_result| matches!(battle_result.outcome, BattleOutcome::Rout(_))).count() as u32,
+            covered_attacks: self.battle_result_list.iter().map(|battle_result| battle_result.covered_attacks as u32).sum(),
+            targeting: self.targeting.clone(),    
         };
         Some(battle_collection_summary)
     }
@@ -593,29 +1705,68 @@ impl Summary<CollectionSummary> for BattleResultCollection {
 impl Accumulate<CollectionAccumulation> for BattleResultCollection {
 
     fn accumulate_summary(&self) -> Option<CollectionAccumulation>{
-        let mut number_of_battles: u32 = 0;
         let mut accumulation:HashMap<(u16,String),u32> = HashMap::new();
 
         for battle in &self.battle_result_list {
-            number_of_battles += 1;
             let res = battle.summarize();
 
             if let Some(battle_summary) = res {
                 let winner = if battle_summary.winner == battle_summary.initiative_winner { 
-                    format!("{}*", battle_summary.winning_team)
+                    format!("{}*", battle_summary.outcome)
                 }
-                else { format!("{}", battle_summary.winning_team) };
+                else { format!("{}", battle_summary.outcome) };
                     *accumulation.entry((battle.turns_run as u16,winner)).or_insert(0) += 1;
             }
         }
         let battle_collection_accumulation = CollectionAccumulation{
-            number_of_battles,
             accumulation,
         };
         Some(battle_collection_accumulation)
     }
 }
 
+struct DayResult {
+    /// Index of the encounter the party lost, if it made it through the day.
+    failed_at: Option<usize>,
+}
+
+#[derive(Default)]
+struct DayResultCollection {
+    arena_id: u8,
+    day_count: u32,
+    stages: Vec<String>,
+    day_result_list: Vec<DayResult>,
+}
+
+struct DaySummary {
+    arena_id: u8,
+    day_count: u32,
+    survived: u32,
+    failures: Vec<(String, u32)>,
+}
+
+impl fmt::Display for DaySummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let failures: Vec<String> = self.failures.iter().map(|(stage, count)| format!("{}:{}", stage, count)).collect();
+        write!(f, "{},{},{},{}", self.arena_id, self.day_count, self.survived, failures.join(","))
+    }
+}
+
+impl Summary<DaySummary> for DayResultCollection {
+    fn summarize(&self) -> Option<DaySummary> {
+        let failures = self.stages.iter().enumerate()
+            .map(|(stage_number, stage)| (stage.clone(),
+                self.day_result_list.iter().filter(|day| day.failed_at == Some(stage_number)).count() as u32))
+            .collect();
+        Some(DaySummary {
+            arena_id: self.arena_id,
+            day_count: self.day_count,
+            survived: self.day_result_list.iter().filter(|day| day.failed_at.is_none()).count() as u32,
+            failures,
+        })
+    }
+}
+
 #[derive(Default, Debug, Clone)]
 struct BattleResult{
     battle_id: String,
@@ -623,6 +1774,11 @@ struct BattleResult{
     winner: CharacterStruct,
     initiative_winner: String,
     turn_result: Vec<TurnResult>,
+    crit_hits: u16,
+    crit_misses: u16,
+    /// Attacks and saves where the target had cover.
+    covered_attacks: u16,
+    outcome: BattleOutcome,
 }
 
 struct BattleSummary {
@@ -630,12 +1786,15 @@ struct BattleSummary {
     turns_run: u8,
     winner: String,
     initiative_winner: String,
-    winning_team: Team,
+    outcome: BattleOutcome,
+    crit_hits: u16,
+    crit_misses: u16,
+    covered_attacks: u16,
 }
 
 impl fmt::Display for BattleSummary {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{},{},{},{:?}", self.battle_id, self.turns_run, self.initiative_winner, self.winning_team)
+        write!(f, "{},{},{},{},{},{},{}", self.battle_id, self.turns_run, self.initiative_winner, self.outcome, self.crit_hits, self.crit_misses, self.covered_attacks)
     }
 }
 
@@ -643,22 +1802,31 @@ impl Summary<BattleSummary> for BattleResult {
     fn summarize(&self) -> Option<BattleSummary> {
         let battle_summary = BattleSummary {
             battle_id: self.battle_id.clone(), 
-            turns_run: self.turns_run as u8, 
-            winner: self.winner.team.to_string(),
+            turns_run: self.turns_run, 
+            winner: self.outcome.winner().map(|team| team.to_string()).unwrap_or_default(),
             initiative_winner: self.initiative_winner.clone(),
-            winning_team: self.winner.team,
+            outcome: self.outcome,
+            crit_hits: self.crit_hits,
+            crit_misses: self.crit_misses,
+            covered_attacks: self.covered_attacks,
         };
         Some(battle_summary)
     }
 }
 
+/// Where the combatant called `name` stands in the battle order.
+#[cfg(test)]
+fn index_of(combatants: &[BattleOrder], name: &str) -> usize {
+    combatants.iter().position(|c| c.character.name == name).unwrap()
+}
+
 
 #[test]
 fn test_make_battle_order_list() {
     use characterize::get_players;
 
     let players = get_players(); 
-    let test_list = make_battle_order_list(&players, &ReportOutputLevel::Summary);
+    let test_list = make_battle_order_list(&players, Rules::default(), &Arc::new(Policies::default()));
     assert_ne!(test_list.battle_order_list.len(),0,"no list");
 }
 
@@ -667,8 +1835,10 @@ fn test_order_of_make_battler_order_list() {
     use characterize::get_players;
 
     let players = get_players();
-    let test_list = make_battle_order_list(&players, &ReportOutputLevel::Summary);
-    assert_eq!(test_list.battle_order_list[0].initative_roll > test_list.battle_order_list[1].initative_roll,true,"list not ordered");
+    let test_list = make_battle_order_list(&players, Rules::default(), &Arc::new(Policies::default()));
+    for pair in test_list.battle_order_list.windows(2) {
+        assert!((pair[0].initative_roll, pair[0].character.dexterity_modifier) >= (pair[1].initative_roll, pair[1].character.dexterity_modifier),"list not ordered");
+    }
 }
 
 #[test]
@@ -684,8 +1854,8 @@ fn select_target_test() {
     use characterize::get_players;
 
     let players = get_players();
-    let test_list = make_battle_order_list(&players, &ReportOutputLevel::Summary);
-    let target = test_list.battle_order_list[0].character.select_target(&test_list.battle_order_list);
+    let test_list = make_battle_order_list(&players, Rules::default(), &Arc::new(Policies::default()));
+    let target = test_list.battle_order_list[0].get_target(&test_list.battle_order_list, Rules::default().ruleset());
 
     assert_ne!(target,Some(0),"select_target selected self");
     match target {
@@ -718,7 +1888,7 @@ fn take_damage_test() {
     let mut actor = players[0].clone();
     let original_health_state = actor.hs2;
 
-    actor = actor.take_damage(5);
+    actor = actor.take_damage(5, Rules::default().ruleset());
     assert_ne!(original_health_state,actor.hs2);
 }
 
@@ -727,14 +1897,14 @@ fn is_winner_test() {
     use characterize::get_players;
 
     let players = get_players();
-    let order_list = make_battle_order_list(&players, &ReportOutputLevel::Summary);
+    let order_list = make_battle_order_list(&players, Rules::default(), &Arc::new(Policies::default()));
 
-    assert_eq!(order_list.is_there_a_winner(),false);
+    assert!(order_list.rules.ruleset().victory(&order_list.battle_order_list).is_none());
 
     let one_list = vec!(players[0].clone());
-    let one_order_list = make_battle_order_list(&one_list, &ReportOutputLevel::Summary);
+    let one_order_list = make_battle_order_list(&one_list, Rules::default(), &Arc::new(Policies::default()));
 
-    assert_eq!(one_order_list.is_there_a_winner(),true);
+    assert!(one_order_list.rules.ruleset().victory(&one_order_list.battle_order_list).is_some());
 }
 
 #[test]
@@ -744,13 +1914,1070 @@ fn is_attack_successful() {
     let players = get_players();
     let successful_attack = AttackResult {
         attack_roll: 20,
+        natural_roll: 20,
+        other_roll: None,
+        critical: false,
         _roll_string: "1d12".to_string(),
     };
     let failure_attack = AttackResult {
         attack_roll: 1,
+        natural_roll: 1,
+        other_roll: None,
+        critical: false,
         _roll_string: "1d12".to_string(),
     };
 
-    assert_eq!(players[0].is_attack_successful(&successful_attack),true);
-    assert_eq!(players[0].is_attack_successful(&failure_attack),false);
-}
\ No newline at end of file
+    let ruleset = Rules::default().ruleset();
+    assert_eq!(ruleset.attack_outcome(players[0].armour_class, &successful_attack), ActionResultType::Hit);
+    assert_eq!(ruleset.attack_outcome(players[0].armour_class, &failure_attack), ActionResultType::CritMiss);
+}
+
+#[test]
+fn load_players_formats_test() {
+    let summary = |players: Vec<CharacterStruct>| players.into_iter()
+        .map(|p| (p.name, p.armour_class, p.to_hit, p.damage, p.team, p.hs2))
+        .collect::<Vec<_>>();
+    let json_players = summary(load_players("./input/temp.json", None).unwrap());
+
+    for file in ["./input/temp.yaml", "./input/temp.toml", "./input/temp.ron"] {
+        let players = summary(load_players(file, None).unwrap());
+        assert_eq!(players, json_players, "{} differs from temp.json", file);
+    }
+}
+
+#[test]
+fn load_players_unknown_format_test() {
+    assert!(load_players("./input/temp.txt", None).is_err());
+}
+
+#[test]
+fn hit_dice_test() {
+    let players = load_players("./input/hit_dice.yaml", None).unwrap();
+    let mut request_cache: RequestCache = Default::default();
+
+    assert_eq!(players[0].hs2, HealthState::Alive(28));
+    assert_eq!(players[1].hs2, HealthState::Alive(27), "5d8+5 should start at its average");
+    assert_eq!(players[3].hs2, HealthState::Alive(3), "2d4-2 should start at its average");
+
+    for _ in 0..100 {
+        let mut bugbear = players[1].clone();
+        bugbear.roll_hit_points(&mut request_cache);
+        match bugbear.hs2 {
+            HealthState::Alive(hit_points) => assert!((10..=45).contains(&hit_points)),
+            _ => panic!("rolled bugbear is not alive"),
+        }
+
+        let mut goblin = players[2].clone();
+        goblin.roll_hit_points(&mut request_cache);
+        assert_eq!(goblin.hs2, HealthState::Alive(7));
+
+        let mut kobold = players[3].clone();
+        kobold.roll_hit_points(&mut request_cache);
+        match kobold.hs2 {
+            HealthState::Alive(hit_points) => assert!((1..=6).contains(&hit_points), "2d4-2 rolled {}", hit_points),
+            _ => panic!("rolled kobold is not alive"),
+        }
+    }
+}
+
+#[test]
+fn roster_templates_test() {
+    let players = load_players("./input/goblins.yaml", None).unwrap();
+    let names: Vec<&str> = players.iter().map(|p| p.name.as_str()).collect();
+
+    assert_eq!(names, vec!("Goblin 1", "Goblin 2", "Goblin 3", "Goblin 4", "Goblin 5", "Goblin 6",
+        "Goblin Boss", "Sword and Board", "Greatsword"));
+    assert_eq!(players[6].armour_class, 17, "override lost");
+    assert_eq!(players[6].weapon, "1d6", "inherited field lost");
+    assert_eq!(players[6].hs2, HealthState::Alive(21));
+    assert_eq!((players[7].armour_class, players[8].armour_class), (18, 16));
+    assert_eq!(players[8].team, Team::Heros);
+}
+
+#[test]
+fn level_progression_test() {
+    let players = load_players("./input/party.yaml", None).unwrap();
+    let fighter = &players[0];
+    assert_eq!(fighter.level, 3);
+    assert_eq!(fighter.hs2, HealthState::Alive(13 + 9 * 2));
+    assert_eq!(fighter.proficiency_bonus, 2);
+    assert_eq!(fighter.actions_per_round, 1);
+    assert!(fighter.features.contains(&"Improved Critical".to_string()));
+
+    let players = load_players("./input/party.yaml", Some(5)).unwrap();
+    let fighter = &players[0];
+    assert_eq!(fighter.hs2, HealthState::Alive(13 + 9 * 4));
+    assert_eq!(fighter.proficiency_bonus, 3);
+    assert_eq!(fighter.actions_per_round, 2);
+    assert!(fighter.features.contains(&"Extra Attack".to_string()));
+    assert_eq!(players[1].hs2, HealthState::Alive(9 + 6 * 4), "rogue");
+    assert_eq!(players[2].level, 0, "goblins have no class to level");
+}
+
+#[test]
+fn parse_args_test() {
+    let args = |list: &[&str]| list.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+
+    let parsed = parse_args(args(&[])).unwrap();
+    assert_eq!((parsed.input_file.as_str(), parsed.level), (DEFAULT_INPUT_FILE, None));
+    let parsed = parse_args(args(&["party.yaml", "--level", "8"])).unwrap();
+    assert_eq!((parsed.input_file.as_str(), parsed.level), ("party.yaml", Some(8)));
+    assert!(parse_args(args(&["--level"])).is_err());
+    assert!(parse_args(args(&["--level", "high"])).is_err());
+    assert_eq!(parse_args(args(&["--max-rounds", "20"])).unwrap().max_rounds, Some(20));
+    assert!(parse_args(args(&["--max-rounds", "0"])).is_err());
+    assert_eq!(parse_args(args(&["--ruleset", "BasicExpert"])).unwrap().rules, Some(Rules::BasicExpert));
+    assert_eq!(parse_args(args(&["--ruleset", "Pathfinder"])).unwrap().rules, Some(Rules::Pathfinder));
+    assert!(parse_args(args(&["--flanking"])).unwrap().flanking);
+    assert!(parse_args(args(&["--ruleset", "Gurps"])).is_err());
+    let parsed = parse_args(args(&["--replay", "battle.jsonl", "--step", "12"])).unwrap();
+    assert_eq!((parsed.replay.as_deref(), parsed.step), (Some("battle.jsonl"), Some(12)));
+    assert_eq!(parse_args(args(&["--event-log", "battle.jsonl"])).unwrap().event_log.as_deref(), Some("battle.jsonl"));
+    assert!(parse_args(args(&["--event-log"])).is_err());
+    assert_eq!(parse_args(args(&["--narrate", "3"])).unwrap().narrate, Some(3));
+    assert!(parse_args(args(&["--narrate", "0"])).is_err());
+
+    let parsed = parse_args(args(&["--targeting", "Random", "--targeting", "Villains=FocusFire"])).unwrap();
+    assert_eq!(parsed.targeting_for(Team::Heros), Some(Targeting::Random));
+    assert_eq!(parsed.targeting_for(Team::Villains), Some(Targeting::FocusFire));
+    assert!(parse_args(args(&["--targeting", "Sideways"])).is_err());
+    assert!(parse_args(args(&["--targeting", "Orcs=Random"])).is_err());
+}
+
+#[test]
+fn armour_class_test() {
+    let players = load_players("./input/armour.yaml", None).unwrap();
+    let mut cleric = players[0].clone();
+
+    assert_eq!(cleric.armour_class, 14 + 2 + 2 + 2, "medium armour, capped DEX, shield and Shield of Faith");
+    assert_eq!(players[1].armour_class, 19, "heavy armour ignores DEX");
+    assert_eq!(players[2].armour_class, 15, "cover applies to natural armour");
+
+    assert!(!cleric.update_armour_class(3));
+    assert!(cleric.update_armour_class(4), "Shield of Faith should have ended");
+    assert_eq!(cleric.armour_class, 18);
+}
+
+#[test]
+fn initiative_rerolled_per_battle_test() {
+    use characterize::get_players;
+
+    let mut players = get_players();
+    players[0].dexterity_modifier = 5;
+    let mut first_places = HashMap::new();
+
+    for _ in 0..200 {
+        let leader = make_battle_order_list(&players, Rules::default(), &Arc::new(Policies::default())).battle_order_list[0].character.name.clone();
+        *first_places.entry(leader).or_insert(0) += 1;
+    }
+    assert_eq!(first_places.len(), 3, "every combatant should win initiative sometimes: {:?}", first_places);
+    assert!(first_places["Hero"] > first_places["Villan-A"], "DEX bonus should win initiative more often");
+
+    // each battle logs initiative rolls of its own
+    let encounter = Encounter { characters: players, ..Default::default() };
+    let rolls: HashSet<Vec<u8>> = (0..10)
+        .map(|battle_num| {
+            let battle = recorded_battle(&encounter, &Arc::new(Policies::default()), battle_num).unwrap();
+            let mut rolls: Vec<u8> = battle.event_log.events().iter()
+                .filter_map(|event| match event {
+                    Event::InitiativeRolled { roll, .. } => Some(*roll),
+                    _ => None,
+                })
+                .collect();
+            assert_eq!(rolls.len(), 3, "one roll per combatant");
+            rolls.sort();
+            rolls
+        })
+        .collect();
+    assert!(rolls.len() > 1, "initiative was rolled once and reused: {:?}", rolls);
+}
+
+#[test]
+fn targeting_strategies_test() {
+    use characterize::get_players;
+
+    let mut players = get_players();
+    players[0].targeting = Targeting::LowestHp;
+    players[1].hs2 = HealthState::Alive(5);
+    players[2].hs2 = HealthState::Alive(2);
+    players[2].armour_class = 14;
+    players[1].damage = 12;
+
+    let mut combatants = make_battle_order_list(&players, Rules::default(), &Arc::new(Policies::default())).battle_order_list;
+    let hero = combatants.iter().find(|c| c.character.name == "Hero").unwrap().clone();
+    let ruleset = Rules::default().ruleset();
+    let select = |targeting: Targeting, combatants: &[BattleOrder]| targeting.strategy().select_target(&hero, combatants, ruleset);
+
+    assert_eq!(select(Targeting::LowestHp, &combatants), Some(index_of(&combatants, "Villan-B")));
+    assert_eq!(select(Targeting::HighestThreat, &combatants), Some(index_of(&combatants, "Villan-A")));
+    assert_eq!(select(Targeting::LowestArmourClass, &combatants), Some(index_of(&combatants, "Villan-A")));
+    assert_eq!(hero.get_target(&combatants, ruleset), Some(index_of(&combatants, "Villan-B")), "character's own strategy");
+
+    let villan_b = index_of(&combatants, "Villan-B");
+    combatants.iter_mut().find(|c| c.character.name == "Villan-A").unwrap().current_target = Some(villan_b);
+    let mut ally = combatants[0].clone();
+    ally.character.name = "Hero-B".to_string();
+    ally.character.team = Team::Heros;
+    ally.team = Team::Heros;
+    ally.current_target = Some(index_of(&combatants, "Villan-A"));
+    combatants.push(ally);
+    assert_eq!(select(Targeting::FocusFire, &combatants), Some(index_of(&combatants, "Villan-A")));
+    assert_eq!(select(Targeting::SpreadDamage, &combatants), Some(index_of(&combatants, "Villan-B")));
+
+    // the hero's own target is no sign of allies piling on
+    let mut committed = hero.clone();
+    committed.current_target = Some(villan_b);
+    let hero_index = index_of(&combatants, "Hero");
+    combatants[hero_index].current_target = Some(villan_b);
+    let select_committed = |targeting: Targeting, combatants: &[BattleOrder]| targeting.strategy().select_target(&committed, combatants, ruleset);
+    assert_eq!(select_committed(Targeting::FocusFire, &combatants), Some(index_of(&combatants, "Villan-A")));
+    assert_eq!(select_committed(Targeting::SpreadDamage, &combatants), Some(villan_b));
+
+    // a 1d6 hit can't finish off the downed under 5e, but can under Pathfinder
+    combatants[villan_b].character.hs2 = HealthState::Ko;
+    assert_eq!(select(Targeting::FinishOffDowned, &combatants), Some(index_of(&combatants, "Villan-A")));
+    assert_eq!(Targeting::FinishOffDowned.strategy().select_target(&hero, &combatants, Rules::Pathfinder.ruleset()), Some(villan_b));
+    let mut brute = hero.clone();
+    brute.character.damage = 12;
+    assert_eq!(Targeting::FinishOffDowned.strategy().select_target(&brute, &combatants, ruleset), Some(villan_b), "more than 10 damage kills the downed under 5e");
+    assert_eq!(select(Targeting::Random, &combatants), Some(index_of(&combatants, "Villan-A")), "only one conscious enemy left");
+}
+
+#[test]
+fn combat_policy_test() {
+    use characterize::get_players;
+    use policy::CombatPolicy;
+
+    struct AlwaysDodge;
+    impl CombatPolicy for AlwaysDodge {
+        fn choose_action(&self, _view: &BattleView) -> Action { Action::Dodge }
+    }
+
+    let mut players = get_players();
+    players[0].policy = Some("AlwaysDodge".to_string());
+    let mut policies = Policies::default();
+    assert!(policies.check(&players).is_err());
+    policies.register("AlwaysDodge", Arc::new(AlwaysDodge));
+    assert!(policies.check(&players).is_ok());
+    let mut encounter = load_encounter("./input/necromancer.yaml", None).unwrap();
+    let necromancer = encounter.characters.iter_mut().find(|c| c.name == "Necromancer").unwrap();
+    necromancer.summons[0].creatures[0].policy = Some("AlwaysDodgy".to_string());
+    assert_eq!(policies.check(encounter.every_character()), Err("Zombie uses unknown policy AlwaysDodgy".to_string()), "summons are checked too");
+    let mut day = load_encounter("./input/day.yaml", None).unwrap();
+    day.day.last_mut().unwrap().characters[0].policy = Some("AlwaysDodgy".to_string());
+    assert!(policies.check(&day.characters).is_ok());
+    assert!(policies.check(day.every_character()).is_err(), "so are the day's later stages");
+
+    let battle = make_battle_order_list(&players, Rules::default(), &Arc::new(policies));
+    let mut request_cache: RequestCache = Default::default();
+    let hero = battle.battle_order_list.iter().position(|c| c.character.name == "Hero").unwrap();
+    let (battle, _) = battle.run_battle_turn(1, &mut request_cache);
+    assert!(battle.battle_order_list[hero].current_target.is_none(), "the dodging hero attacked");
+
+    players[1].hs2 = HealthState::Alive(2);
+    players[2].hs2 = HealthState::Alive(6);
+    players[2].armour_class = 1;
+    let combatants = make_battle_order_list(&players, Rules::default(), &Arc::new(Policies::default())).battle_order_list;
+    let actor = combatants.iter().position(|c| c.character.name == "Hero").unwrap();
+    let view = BattleView { combatants: &combatants, actor, ruleset: Rules::default().ruleset() };
+    let villan_b = combatants.iter().position(|c| c.character.name == "Villan-B").unwrap();
+    assert_eq!(policy::GreedyDamage.choose_action(&view), Action::Attack(villan_b), "easy to hit with more hit points to take");
+    assert!(view.available_actions().contains(&Action::Dodge));
+
+    let mut fortress = combatants[villan_b].character.clone();
+    fortress.armour_class = 60;
+    assert!(combatants[actor].hit_chance(&fortress, Rules::default().ruleset()) > 0.0, "a natural 20 always crits under 5e");
+    assert_eq!(combatants[actor].hit_chance(&fortress, Rules::Pathfinder.ruleset()), 0.0, "a natural 20 only lifts a critical failure to a miss");
+}
+
+#[test]
+fn critical_hit_test() {
+    use characterize::get_players;
+
+    let players = get_players();
+    let defender = &players[0];
+    let ruleset = Rules::default().ruleset();
+    let attack = |natural_roll: u8, attack_roll: u8, critical: bool| AttackResult {
+        attack_roll, natural_roll, other_roll: None, critical, _roll_string: "d20".to_string(),
+    };
+
+    assert_eq!(ruleset.attack_outcome(defender.armour_class, &attack(12, 12, false)), ActionResultType::Hit, "meeting AC hits");
+    assert_eq!(ruleset.attack_outcome(defender.armour_class, &attack(11, 11, false)), ActionResultType::Miss);
+    assert_eq!(ruleset.attack_outcome(defender.armour_class, &attack(1, 30, false)), ActionResultType::CritMiss, "natural 1 always misses");
+    assert_eq!(ruleset.attack_outcome(defender.armour_class, &attack(20, 5, true)), ActionResultType::CritHit, "a crit always hits");
+
+    let mut champion = players[1].clone();
+    assert_eq!(champion.crit_threshold(), 20);
+    champion.features.push("Improved Critical".to_string());
+    assert_eq!(champion.crit_threshold(), 19);
+    champion.crit_range = Some(17);
+    assert_eq!(champion.crit_threshold(), 17);
+
+    let request = dice_thrower::parse_request("2d6+3").unwrap();
+    for _ in 0..100 {
+        let damage = dice_thrower::throw_critical_roll(&request);
+        assert!((7..=27).contains(&damage), "crits double the dice, not the modifier");
+    }
+
+    champion.damage = 200;
+    let champion = enter_battle(&champion, 0);
+    let mut request_cache: RequestCache = Default::default();
+    let damage: Vec<u16> = (0..100).map(|_| champion.get_damage(Rules::Pathfinder.ruleset(), &mut request_cache, true).damage).collect();
+    assert!(damage.iter().all(|damage| (2..=400).contains(damage)) && damage.iter().any(|damage| *damage > 255), "doubled damage doesn't wrap: {:?}", damage);
+}
+
+#[test]
+fn standard_actions_test() {
+    use characterize::get_players;
+    use policy::CombatPolicy;
+
+    struct Always(Action);
+    impl CombatPolicy for Always {
+        fn choose_action(&self, _view: &BattleView) -> Action { self.0 }
+    }
+
+    assert_eq!(RollMode::combine(true, false), RollMode::Advantage);
+    assert_eq!(RollMode::combine(false, true), RollMode::Disadvantage);
+    assert_eq!(RollMode::combine(true, true), RollMode::Normal);
+
+    let mut players = get_players();
+    players[0].policy = Some("Dodge".to_string());
+    players[0].hs2 = HealthState::Alive(500);
+    let mut policies = Policies::default();
+    policies.register("Dodge", Arc::new(Always(Action::Dodge)));
+    let battle = make_battle_order_list(&players, Rules::default(), &Arc::new(policies));
+    let hero = battle.battle_order_list.iter().position(|c| c.character.name == "Hero").unwrap();
+    let mut request_cache: RequestCache = Default::default();
+    let (battle, _) = battle.run_battle_turn(1, &mut request_cache);
+    assert!(battle.battle_order_list[hero].dodging, "dodge lasts until the hero's next turn");
+
+    let mut combatants = battle.battle_order_list;
+    let villan = combatants.iter().position(|c| c.character.name == "Villan-A").unwrap();
+    for other in combatants.iter_mut() {
+        other.character.passive_perception = Some(0);
+    }
+    let hide = BattleOrderList::attempt_hide(&mut combatants, villan, &mut request_cache, &mut EventLog::default());
+    assert_eq!(hide.action_result, ActionResultType::Hit);
+    assert!(combatants[villan].hidden);
+
+    combatants[villan].helped_by = Some(hero);
+    BattleOrderList::resolve_attack(&mut combatants, villan, hero, None, Rules::default().ruleset(), false, &mut request_cache, &mut EventLog::default());
+    assert!(!combatants[villan].hidden, "attacking reveals the attacker");
+    assert_eq!(combatants[villan].helped_by, None, "help is used up by the attack");
+
+    let total: u32 = (0..2000)
+        .map(|_| combatants[villan].make_attack(&mut request_cache, RollMode::Disadvantage).natural_roll as u32)
+        .sum();
+    assert!(total / 2000 < 9, "disadvantage should pull the natural roll down");
+}
+
+#[test]
+fn advantage_test() {
+    use characterize::get_players;
+    use AdvantageSource::*;
+
+    assert_eq!(RollMode::resolve(&[]), RollMode::Normal);
+    assert_eq!(RollMode::resolve(&[Helped, FaerieFire]), RollMode::Advantage);
+    assert_eq!(RollMode::resolve(&[Helped, RecklessAttack, TargetDodging]), RollMode::Normal, "one disadvantage cancels any advantage");
+    assert_eq!(RollMode::resolve(&[LongRange, AttackerProne]), RollMode::Disadvantage);
+
+    let mut combatants = make_battle_order_list(&get_players(), Rules::default(), &Arc::new(Policies::default())).battle_order_list;
+    let (hero, villan) = (index_of(&combatants, "Hero"), index_of(&combatants, "Villan-A"));
+    assert!(combatants[hero].advantage_sources(villan, &combatants, false).is_empty());
+
+    combatants[hero].character.features.push("Reckless Attack".to_string());
+    combatants[hero].reckless = true;
+    combatants[villan].prone = true;
+    combatants[villan].outlined = true;
+    assert_eq!(combatants[hero].advantage_sources(villan, &combatants, false), vec![RecklessAttack, TargetProne, FaerieFire]);
+    assert_eq!(combatants[villan].advantage_sources(hero, &combatants, false), vec![TargetReckless, AttackerProne]);
+    combatants[hero].reaction_used = true;
+    assert!(!combatants[hero].advantage_sources(villan, &combatants, false).contains(&RecklessAttack), "not on an opportunity attack");
+
+    // cancelled sources still roll one die, but the log says why
+    combatants[villan].dodging = true;
+    let mut event_log = EventLog::recording();
+    let mut request_cache: RequestCache = Default::default();
+    BattleOrderList::resolve_attack(&mut combatants, hero, villan, None, Rules::default().ruleset(), false, &mut request_cache, &mut event_log);
+    match &event_log.events()[0] {
+        Event::AttackRolled { rolls, sources, .. } => {
+            assert_eq!(rolls.len(), 1);
+            assert_eq!(sources, &vec![TargetProne, FaerieFire, TargetDodging]);
+        }
+        other => panic!("expected an attack, got {:?}", other),
+    }
+
+    // the unconscious fall prone
+    combatants[hero].character.hs2 = HealthState::Alive(1);
+    BattleOrderList::apply_damage(&mut combatants, hero, 1, Rules::default().ruleset(), &mut event_log);
+    assert!(combatants[hero].prone);
+
+    let encounter = load_encounter("./input/advantage.yaml", None).unwrap();
+    let battle = recorded_battle(&encounter, &Arc::new(Policies::default()), 0).unwrap();
+    let barbarian = index_of(&battle.battle_order_list, "Barbarian");
+    let bugbear = index_of(&battle.battle_order_list, "Bugbear");
+    let mut round = 0;
+    for event in battle.event_log.events() {
+        match event {
+            Event::RoundStarted { round: started } => round = *started,
+            Event::AttackRolled { attacker, target, rolls, sources, .. } => {
+                assert_eq!(rolls.len() == 2, RollMode::resolve(sources) != RollMode::Normal);
+                assert_eq!(*attacker == barbarian, sources.contains(&RecklessAttack));
+                assert_eq!(*target == bugbear && round <= 10, sources.contains(&FaerieFire), "outlined for ten rounds");
+            }
+            _ => (),
+        }
+    }
+}
+
+#[test]
+fn healing_test() {
+    use characterize::HealPriority;
+    use policy::CombatPolicy;
+
+    let encounter = load_encounter("./input/healing.yaml", None).unwrap();
+    let mut combatants = make_battle_order_list(&encounter.characters, Rules::default(), &Arc::new(Policies::default())).battle_order_list;
+    for order in combatants.iter_mut() {
+        order.max_hp = order.character.hit_points();
+    }
+    let (fighter, paladin, cleric) = (index_of(&combatants, "Fighter"), index_of(&combatants, "Paladin"), index_of(&combatants, "Cleric"));
+
+    // nobody is hurt, so nobody heals
+    let view = BattleView { combatants: &combatants, actor: cleric, ruleset: Rules::default().ruleset() };
+    assert_eq!(view.wounded().count(), 0);
+    assert_eq!(view.patient(), None);
+
+    // downed allies come first, then whoever is furthest under the threshold
+    combatants[fighter].character.hs2 = HealthState::Alive(5);
+    combatants[paladin].character.hs2 = HealthState::Alive(10);
+    let view = BattleView { combatants: &combatants, actor: cleric, ruleset: Rules::default().ruleset() };
+    assert_eq!(view.patient(), Some(fighter));
+    assert_eq!(policy::RuleBased.choose_action(&view), Action::Heal(fighter));
+    assert!(view.available_actions().contains(&Action::Heal(paladin)));
+    let view = BattleView { combatants: &combatants, actor: paladin, ruleset: Rules::default().ruleset() };
+    assert_eq!(view.patient(), None, "only downed allies by default");
+    combatants[paladin].character.hs2 = HealthState::Ko;
+    let view = BattleView { combatants: &combatants, actor: cleric, ruleset: Rules::default().ruleset() };
+    assert_eq!(view.patient(), Some(paladin));
+    combatants[cleric].character.heal_priority = HealPriority::Never;
+    let view = BattleView { combatants: &combatants, actor: cleric, ruleset: Rules::default().ruleset() };
+    assert_eq!(view.patient(), None);
+    let view = BattleView { combatants: &combatants, actor: fighter, ruleset: Rules::default().ruleset() };
+    assert_eq!(view.patient(), Some(fighter), "Second Wind only heals the fighter");
+
+    // the downed get back up, but never past their starting hit points
+    let mut event_log = EventLog::recording();
+    let mut request_cache: RequestCache = Default::default();
+    BattleOrderList::resolve_heal(&mut combatants, cleric, 1, paladin, &mut request_cache, &mut event_log);
+    assert!(matches!(combatants[paladin].character.hs2, HealthState::Alive(4..=11)));
+    assert_eq!(combatants[cleric].character.heals[1].uses, Some(1));
+    assert!(matches!(event_log.events(), [Event::Healed { .. }, Event::StateChanged { to: HealthState::Alive(_), .. }]));
+    assert_eq!(combatants[fighter].heal(100), 15);
+    assert_eq!(combatants[fighter].character.hs2, HealthState::Alive(20));
+    combatants[fighter].character.hs2 = HealthState::Dead;
+    assert_eq!(combatants[fighter].heal(10), 0, "the dead stay dead");
+
+    // a pool spends only what's needed
+    combatants[cleric].character.hs2 = HealthState::Alive(13);
+    let result = BattleOrderList::resolve_heal(&mut combatants, paladin, 0, cleric, &mut request_cache, &mut event_log);
+    assert_eq!(result.action_roll, 4);
+    assert_eq!(combatants[paladin].character.heals[0].pool, Some(11));
+    let mut rested = combatants[paladin].character.clone();
+    rested.heals[0].pool = Some(15);
+    combatants[paladin].character.long_rest(&rested);
+    assert_eq!(combatants[paladin].character.heals[0].pool, Some(15));
+
+    // heals are used up and the log replays to where the battle ended
+    for battle_num in 0..10 {
+        let battle = recorded_battle(&encounter, &Arc::new(Policies::default()), battle_num).unwrap();
+        let events = battle.event_log.events();
+        let end = event_log::replay(events, events.len()).unwrap();
+        for (state, order) in end.combatants.iter().zip(&battle.battle_order_list) {
+            assert_eq!(state.health, order.character.hs2);
+            assert!(order.character.hit_points() <= order.max_hp);
+        }
+        let cleric = &battle.battle_order_list[index_of(&battle.battle_order_list, "Cleric")];
+        let heals_used = events.iter().filter(|event| matches!(event, Event::Healed { healer, .. } if battle.battle_order_list[*healer].character.name == "Cleric")).count();
+        let uses_left: u8 = cleric.character.heals.iter().filter_map(|heal| heal.uses).sum();
+        assert_eq!(heals_used + uses_left as usize, 5);
+    }
+}
+
+#[test]
+fn battlefield_movement_test() {
+    let encounter = load_encounter("./input/skirmish.yaml", None).unwrap();
+    let battlefield = encounter.battlefield.clone().expect("skirmish has a battlefield");
+    let mut battle = make_battle_order_list(&encounter.characters, Rules::default(), &Arc::new(Policies::default()));
+    battle.battlefield = Some(battlefield.clone());
+    battle.place_combatants(&battlefield);
+    let mut combatants = battle.battle_order_list.clone();
+    let (fighter, archer, ogre) = (index_of(&combatants, "Fighter"), index_of(&combatants, "Archer"), index_of(&combatants, "Ogre"));
+
+    assert_eq!(combatants[ogre].position, Some((17, 4)));
+    assert!(!combatants[fighter].in_range(&combatants[ogre]));
+    assert!(combatants[archer].in_range(&combatants[ogre]), "within long range");
+    assert_eq!(combatants[archer].ranged_disadvantage(&combatants[ogre], &combatants), Some(AdvantageSource::LongRange));
+
+    let mut request_cache: RequestCache = Default::default();
+    let mut turn_result: TurnResult = Default::default();
+    let left = battle.close_in(&mut combatants, ogre, fighter, 8, &mut request_cache, &mut turn_result);
+    let square = combatants[ogre].position.unwrap();
+    assert!(left < 8 && square != (17, 4), "the ogre moved");
+    assert!(square.0 != 9 || !battlefield.obstacles.contains(&square));
+    let left = battle.close_in(&mut combatants, ogre, fighter, 8, &mut request_cache, &mut turn_result);
+    assert!(left > 0 && combatants[ogre].in_range(&combatants[fighter]), "two moves close the gap");
+    assert_eq!(combatants[ogre].distance_to(&combatants[fighter]), Some(2), "reach 2 stops short");
+
+    // stepping out of the ogre's reach provokes it, once, unless disengaging
+    combatants[fighter].character.hs2 = HealthState::Alive(500);
+    combatants[fighter].disengaged = true;
+    battle.back_off(&mut combatants, fighter, 6, &mut request_cache, &mut turn_result);
+    assert!(!combatants[ogre].in_range(&combatants[fighter]));
+    assert!(turn_result.action_results.is_empty(), "disengaging avoids opportunity attacks");
+
+    battle.close_in(&mut combatants, ogre, fighter, 8, &mut request_cache, &mut turn_result);
+    combatants[fighter].disengaged = false;
+    battle.back_off(&mut combatants, fighter, 6, &mut request_cache, &mut turn_result);
+    assert_eq!(turn_result.action_results.len(), 1);
+    assert!(matches!(turn_result.action_results[0].action_type, ActionType::OpportunityAttack));
+    assert!(combatants[ogre].reaction_used);
+
+    let result = battle.run_battle(1, &ReportOutputLevel::None);
+    assert!(result.battle_result.outcome.winner().is_some() || result.battle_result.outcome == BattleOutcome::Draw);
+}
+
+#[test]
+fn area_attack_test() {
+    use policy::CombatPolicy;
+
+    let players = load_players("./input/fireball.yaml", None).unwrap();
+    let mut battle = make_battle_order_list(&players, Rules::default(), &Arc::new(Policies::default()));
+    let mut combatants = battle.battle_order_list.clone();
+    let wizard = combatants.iter().position(|c| c.character.name == "Wizard").unwrap();
+    let goblin = combatants.iter().position(|c| c.character.team == Team::Villains).unwrap();
+    let view = BattleView { combatants: &combatants, actor: wizard, ruleset: Rules::default().ruleset() };
+    assert_eq!(policy::RuleBased.choose_action(&view), Action::Cast(goblin));
+    assert!(view.available_actions().contains(&Action::Cast(goblin)));
+
+    // on a grid the aim has to be within range, after walking, this turn
+    let mut placed = combatants.clone();
+    let (mut villains, mut heros) = (0, 0);
+    for order in placed.iter_mut() {
+        let square = if order.team == Team::Villains { villains += 1; (40 + villains, 0) } else { heros += 1; (0, heros) };
+        order.position = Some(square);
+    }
+    let view = BattleView { combatants: &placed, actor: wizard, ruleset: Rules::default().ruleset() };
+    assert!(!view.worth_an_area_attack(goblin), "41 squares is beyond 30 of range and 6 of speed");
+    placed[wizard].character.area_attacks[0].range = 40;
+    let view = BattleView { combatants: &placed, actor: wizard, ruleset: Rules::default().ruleset() };
+    assert!(view.worth_an_area_attack(goblin));
+
+    let fireball = combatants[wizard].character.area_attacks[0].clone();
+    let caught = combatants[wizard].area_targets(&fireball, goblin, &combatants);
+    assert_eq!(caught.len(), 4, "a 20 foot radius catches four without a battlefield");
+    assert!(caught.iter().all(|i| combatants[*i].team == Team::Villains));
+
+    for goblin in combatants.iter_mut().filter(|c| c.team == Team::Villains) {
+        goblin.character.hs2 = HealthState::Alive(100);
+    }
+    combatants[wizard].character.area_attacks[0].save_dc = 30;
+    let mut request_cache: RequestCache = Default::default();
+    let results = battle.resolve_area_attack(&mut combatants, wizard, 0, goblin, &mut request_cache);
+    assert_eq!(results.len(), 4, "one result per creature caught");
+    assert!(results.iter().all(|r| r.action_result == ActionResultType::Hit && r.action_damage == results[0].action_damage),
+        "damage is rolled once and nobody can make a DC 30 save");
+    assert!((8..=48).contains(&results[0].action_damage));
+
+    combatants[wizard].character.area_attacks[0].save_dc = 0;
+    let results = battle.resolve_area_attack(&mut combatants, wizard, 0, goblin, &mut request_cache);
+    assert!(results.iter().all(|r| r.action_result == ActionResultType::Miss && r.action_damage <= 24), "saves halve the damage");
+    assert_eq!(combatants[wizard].character.usable_area_attack(), None, "both fireballs used");
+
+    let view = BattleView { combatants: &combatants, actor: wizard, ruleset: Rules::default().ruleset() };
+    assert_eq!(policy::RuleBased.choose_action(&view), Action::Attack(goblin));
+    assert!(!view.available_actions().iter().any(|action| matches!(action, Action::Cast(_))), "nothing left to cast");
+}
+
+#[test]
+fn battle_outcome_test() {
+    use characterize::get_players;
+
+    let mut players = get_players();
+    players.truncate(2);
+    for player in players.iter_mut() {
+        player.to_hit = 10;
+        player.armour_class = 20;
+    }
+    let battle = make_battle_order_list(&players, Rules::default(), &Arc::new(Policies::default())).run_battle(1, &ReportOutputLevel::None);
+    assert_eq!(battle.battle_result.outcome, BattleOutcome::Stalemate, "a d10 can neither crit nor reach AC 20");
+    assert_eq!(battle.battle_result.turns_run, 1);
+
+    for player in players.iter_mut() {
+        player.to_hit = 20;
+        player.hs2 = HealthState::Alive(10_000);
+    }
+    let mut battle = make_battle_order_list(&players, Rules::default(), &Arc::new(Policies::default()));
+    battle.max_rounds = Some(3);
+    let battle = battle.run_battle(2, &ReportOutputLevel::None);
+    assert_eq!(battle.battle_result.outcome, BattleOutcome::Timeout);
+    assert_eq!(battle.battle_result.turns_run, 3);
+
+    let mut knocked_out = battle.clone();
+    for order in knocked_out.battle_order_list.iter_mut() {
+        order.character.hs2 = HealthState::Ko;
+    }
+    assert!(knocked_out.get_winner().is_none());
+    assert_eq!(knocked_out.outcome(1, 3), Some(BattleOutcome::Draw), "mutual knock out");
+    knocked_out.battle_order_list[0].character.hs2 = HealthState::Alive(1);
+    let survivor = knocked_out.battle_order_list[0].team;
+    assert_eq!(knocked_out.outcome(1, 3), Some(BattleOutcome::Victory(survivor)));
+
+    let collection = BattleResultCollection {
+        battle_count: 2,
+        battle_result_list: vec![battle.battle_result.clone(), BattleResult { outcome: BattleOutcome::Draw, ..Default::default() }],
+        ..Default::default()
+    };
+    let summary = collection.summarize().unwrap();
+    assert_eq!((summary.draws, summary.stalemates, summary.timeouts), (1, 0, 1));
+}
+
+#[test]
+fn morale_test() {
+    let players = load_players("./input/rout.yaml", None).unwrap();
+    let mut battle = make_battle_order_list(&players, Rules::default(), &Arc::new(Policies::default()));
+    let combatants = &mut battle.battle_order_list;
+    for order in combatants.iter_mut() {
+        order.max_hp = order.character.hit_points();
+    }
+    let (goblin, boss, fighter) = (index_of(combatants, "Goblin 1"), index_of(combatants, "Goblin Boss"), index_of(combatants, "Fighter"));
+
+    assert_eq!(combatants[goblin].morale_breaks(combatants), None);
+    combatants[goblin].character.hs2 = HealthState::Alive(combatants[goblin].max_hp / 2 - 1);
+    assert_eq!(combatants[goblin].morale_breaks(combatants), Some(MoraleResponse::Flee), "below half");
+    assert_eq!(combatants[fighter].morale_breaks(combatants), None, "no morale, fights on");
+
+    let goblin_2 = index_of(combatants, "Goblin 2");
+    combatants[boss].character.hs2 = HealthState::Ko;
+    assert_eq!(combatants[goblin_2].morale_breaks(combatants), Some(MoraleResponse::Flee), "the boss is down");
+    combatants[boss].character.hs2 = HealthState::Alive(1);
+    for order in combatants.iter_mut().filter(|c| c.team == Team::Villains && c.character.name != "Goblin Boss") {
+        order.character.hs2 = HealthState::Ko;
+    }
+    assert_eq!(combatants[boss].morale_breaks(combatants), Some(MoraleResponse::Surrender), "the warband is spent");
+    combatants[fighter].character.to_hit = 1; // only natural 1s, so the boss lives to surrender
+
+    let mut request_cache: RequestCache = Default::default();
+    let (battle, _) = battle.run_battle_turn(1, &mut request_cache);
+    assert_eq!(battle.battle_order_list[boss].withdrawn, Some(MoraleResponse::Surrender));
+    assert_eq!(battle.outcome(1, 10), Some(BattleOutcome::Rout(Team::Heros)));
+    assert_eq!(BattleOutcome::Rout(Team::Heros).to_string(), "Heros by rout");
+}
+
+#[test]
+fn adventuring_day_test() {
+    let encounter = load_encounter("./input/day.yaml", None).unwrap();
+    assert_eq!(encounter.characters.len(), 2, "the party");
+    let names: Vec<&str> = encounter.day.iter().map(|stage| stage.name.as_str()).collect();
+    assert_eq!(names, ["Goblin Ambush", "Orc Patrol", "Warband"]);
+    assert_eq!(encounter.day[0].rest, Some(Rest::Short));
+
+    let mut fresh = encounter.characters[0].clone();
+    fresh.hs2 = HealthState::Alive(28);
+    assert_eq!((fresh.hit_die, fresh.hit_dice), (Some(10), 3));
+    let mut wounded = fresh.clone();
+    wounded.hs2 = HealthState::Alive(2);
+    wounded.short_rest(&fresh);
+    assert!(wounded.hit_points() > 2 && wounded.hit_points() <= 28, "hit dice heal up to the day's starting hit points");
+    assert!(wounded.hit_dice < 3);
+    let mut spent = wounded.clone();
+    spent.hit_dice = 0;
+    spent.hs2 = HealthState::Ko;
+    spent.short_rest(&fresh);
+    assert_eq!(spent.hs2, HealthState::Ko, "no getting up on a short rest");
+    spent.long_rest(&fresh);
+    assert_eq!((spent.hs2, spent.hit_dice), (HealthState::Alive(28), 1));
+
+    let mut wizard = encounter.characters[1].clone();
+    let rested = wizard.clone();
+    wizard.area_attacks[0].uses = Some(0);
+    wizard.short_rest(&rested);
+    assert_eq!(wizard.area_attacks[0].uses, Some(0), "burning hands needs a long rest");
+    wizard.long_rest(&rested);
+    assert_eq!(wizard.area_attacks[0].uses, Some(2));
+
+    let policies = Arc::new(Policies::default());
+    let rested = encounter.characters.clone();
+    let mut party = rested.clone();
+    party[0].hs2 = HealthState::Alive(2);
+    party[1].hs2 = HealthState::Dead;
+    let (battle, fighting) = stage_battle(&encounter, &encounter.day[0], &party, &rested, &policies);
+    assert_eq!(fighting, [0]);
+    let fighter = battle.battle_order_list.iter().find(|order| order.roster_index == Some(0)).unwrap();
+    assert_eq!((fighter.character.hit_points(), fighter.max_hp), (2, rested[0].hit_points()), "wounded, not shrunk");
+    let battle = battle.run_battle(0, &ReportOutputLevel::None);
+    let fighter = battle.battle_order_list.iter().find(|order| order.roster_index == Some(0)).unwrap();
+    assert_eq!(fighter.max_hp, rested[0].hit_points());
+
+    let days: Vec<DayResult> = (0..20).map(|day| run_day(&encounter, &policies, day, &ReportOutputLevel::None)).collect();
+    let collection = DayResultCollection {
+        day_count: 20,
+        stages: names.iter().map(|name| name.to_string()).collect(),
+        day_result_list: days,
+        ..Default::default()
+    };
+    let summary = collection.summarize().unwrap();
+    assert_eq!(summary.survived + summary.failures.iter().map(|(_, count)| count).sum::<u32>(), 20);
+    assert!(summary.to_string().contains("Orc Patrol:"));
+}
+
+#[test]
+fn reinforcements_test() {
+    use policy::CombatPolicy;
+
+    let encounter = load_encounter("./input/necromancer.yaml", None).unwrap();
+    assert_eq!(encounter.reinforcements.len(), 2);
+    let necromancer = encounter.characters.iter().find(|c| c.name == "Necromancer").unwrap();
+    assert_eq!(necromancer.summons[0].creatures[0].name, "Zombie");
+    assert!(necromancer.summons[0].creatures[0].summons.is_empty());
+
+    let mut battle = make_battle_order_list(&encounter.characters, Rules::default(), &Arc::new(Policies::default()));
+    battle.reinforcements = encounter.reinforcements.clone();
+    for order in battle.battle_order_list.iter_mut() {
+        order.max_hp = order.character.hit_points();
+    }
+    let mut request_cache: RequestCache = Default::default();
+    battle.call_reinforcements(2, &mut request_cache);
+    assert_eq!(battle.battle_order_list.len(), 3, "nobody due yet");
+    battle.call_reinforcements(3, &mut request_cache);
+    assert_eq!(battle.battle_order_list.len(), 5, "the skeletons arrive on round 3");
+    assert!(battle.battle_order_list[3..].iter().all(|order| order.character.name.starts_with("Skeleton") && order.max_hp == 13));
+
+    let leader = battle.battle_order_list.iter().position(|c| c.character.name == "Necromancer").unwrap();
+    battle.battle_order_list[leader].character.hs2 = HealthState::Alive(19);
+    battle.call_reinforcements(4, &mut request_cache);
+    assert_eq!(battle.battle_order_list.last().unwrap().character.name, "Zombie Brute", "the necromancer is below half");
+    assert!(battle.reinforcements.is_empty());
+
+    let sequence = BattleOrderList::turn_sequence(&battle.battle_order_list);
+    assert!(sequence.windows(2).all(|pair| battle.battle_order_list[pair[0]].initative_roll >= battle.battle_order_list[pair[1]].initative_roll));
+
+    battle.battle_order_list[leader].character.hs2 = HealthState::Alive(500);
+    let before = battle.battle_order_list.len();
+    let (battle, _) = battle.run_battle_turn(4, &mut request_cache);
+    let after = &battle.battle_order_list;
+    assert_eq!(after.len(), before + 1, "the necromancer raised a zombie");
+    assert_eq!(after[leader].character.summons[0].uses, Some(2));
+    assert_eq!(after.last().unwrap().team, Team::Villains);
+    assert_eq!(after.last().unwrap().summoned_by, Some(leader));
+
+    // unlimited summons wait for the last lot to fall rather than flood the field
+    let mut combatants = after.clone();
+    combatants[leader].character.summons[0].uses = None;
+    let view = BattleView { combatants: &combatants, actor: leader, ruleset: Rules::default().ruleset() };
+    assert!(view.summons_fighting());
+    assert!(matches!(policy::RuleBased.choose_action(&view), Action::Attack(_)), "the zombie is still up");
+    combatants.last_mut().unwrap().character.hs2 = HealthState::Dead;
+    let view = BattleView { combatants: &combatants, actor: leader, ruleset: Rules::default().ruleset() };
+    assert_eq!(policy::RuleBased.choose_action(&view), Action::Summon);
+
+    // nobody can hurt anybody yet, but help is on its way
+    let mut battle = make_battle_order_list(&encounter.characters, Rules::default(), &Arc::new(Policies::default()));
+    battle.reinforcements = encounter.reinforcements.clone();
+    for order in battle.battle_order_list.iter_mut() {
+        order.character.cover = Some(Cover::Total);
+    }
+    assert_eq!(battle.outcome(1, 10), None, "reinforcements and summons are still to come");
+    for order in battle.battle_order_list.iter_mut() {
+        order.character.summons.clear();
+    }
+    assert_eq!(battle.outcome(1, 10), None, "reinforcements are still to come");
+    battle.reinforcements.clear();
+    assert_eq!(battle.outcome(1, 10), Some(BattleOutcome::Stalemate));
+    let leader = index_of(&battle.battle_order_list, "Necromancer");
+    battle.battle_order_list[leader].character.summons = necromancer.summons.clone();
+    assert_eq!(battle.outcome(1, 10), None, "the necromancer can still summon");
+}
+
+#[test]
+fn turn_results_test() {
+    use characterize::get_players;
+
+    let players = get_players();
+    let battle = make_battle_order_list(&players, Rules::default(), &Arc::new(Policies::default())).run_battle(1, &ReportOutputLevel::Turns);
+    let turns = &battle.battle_result.turn_result;
+    assert_eq!(turns.len(), battle.battle_result.turns_run as usize);
+    assert!(turns.iter().enumerate().all(|(i, turn)| turn.turn_number as usize == i + 1));
+    assert!(turns.iter().all(|turn| !turn.action_results.is_empty()), "every round someone acts");
+    let damage: u16 = turns.iter().map(|turn| turn.summarize().unwrap().damage_done).sum();
+    assert!(damage > 0, "somebody won, so somebody took damage");
+
+    let quiet = make_battle_order_list(&players, Rules::default(), &Arc::new(Policies::default())).run_battle(2, &ReportOutputLevel::None);
+    assert!(quiet.battle_result.turn_result.is_empty(), "turns are only kept when asked for");
+
+    let collection = BattleResultCollection {
+        battle_count: 2,
+        battle_result_list: vec![battle.battle_result.clone(), quiet.battle_result.clone()],
+        ..Default::default()
+    };
+    assert_eq!(collection.summarize().unwrap().max_turns_run, battle.battle_result.turns_run.max(quiet.battle_result.turns_run));
+}
+
+#[test]
+fn event_log_test() {
+    for file in &["./input/skirmish.yaml", "./input/necromancer.yaml", "./input/rout.yaml"] {
+        let encounter = load_encounter(file, None).unwrap();
+        let mut battle = new_battle(&encounter, &Arc::new(Policies::default()));
+        battle.event_log = EventLog::recording();
+        let battle = battle.run_battle(1, &ReportOutputLevel::None);
+        let events = event_log::from_json_lines(&battle.event_log.to_json_lines()).unwrap();
+        assert_eq!(events, battle.event_log.events());
+
+        // replaying every event arrives where the battle did
+        let end = event_log::replay(&events, events.len()).unwrap();
+        assert_eq!(end.combatants.len(), battle.battle_order_list.len());
+        for (state, order) in end.combatants.iter().zip(&battle.battle_order_list) {
+            assert_eq!((&state.name, state.health, state.position), (&order.character.name, order.character.hs2, order.position));
+            assert_eq!(state.initiative, Some(order.initative_roll));
+            assert_eq!(state.conditions.contains(&Condition::Dodging), order.dodging, "{}", file);
+        }
+        assert_eq!(end.ended, Some(battle.battle_result.outcome.to_string()));
+        assert_eq!(end.round, battle.battle_result.turns_run);
+
+        for event in &events {
+            if let Event::AttackRolled { rolls, kept, bonus, penalty, total, .. } = event {
+                assert!(rolls.contains(kept) && *total == (kept + bonus).saturating_sub(*penalty));
+                assert_eq!(*penalty, 0, "no multiple attack penalty under 5e");
+            }
+        }
+        let first_blow = events.iter().position(|event| matches!(event, Event::DamageApplied { .. })).unwrap();
+        let before = event_log::replay(&events, first_blow).unwrap();
+        assert!(before.combatants.iter().all(|state| matches!(state.health, HealthState::Alive(_))), "nobody is hurt before the first blow");
+    }
+}
+
+#[test]
+fn ruleset_test() {
+    use characterize::get_players;
+
+    let players = get_players();
+    let defender = &players[0];
+    let attack = |natural_roll: u8, attack_roll: u8, critical: bool| AttackResult {
+        attack_roll, natural_roll, other_roll: None, critical, _roll_string: "d20".to_string(),
+    };
+    let fifth = Rules::FifthEdition.ruleset();
+    let basic = Rules::BasicExpert.ruleset();
+    assert_eq!(basic.attack_outcome(defender.armour_class, &attack(20, 5, true)), ActionResultType::Hit, "no criticals in B/X");
+    assert_eq!(basic.attack_outcome(defender.armour_class, &attack(1, 30, false)), ActionResultType::Miss);
+
+    assert_eq!(fifth.take_damage(HealthState::Alive(5), 5), HealthState::Ko);
+    assert_eq!(fifth.take_damage(HealthState::Alive(5), 6), HealthState::Dead);
+    assert_eq!(fifth.take_damage(HealthState::Ko, 10), HealthState::Ko);
+    assert_eq!(fifth.take_damage(HealthState::Ko, 11), HealthState::Dead);
+    assert_eq!(basic.take_damage(HealthState::Alive(5), 4), HealthState::Alive(1));
+    assert_eq!(basic.take_damage(HealthState::Alive(5), 5), HealthState::Dead, "no knock outs in B/X");
+
+    // sides roll once and never tie, so each acts as a block
+    let encounter = load_encounter("./input/basic_expert.yaml", None).unwrap();
+    assert_eq!(encounter.rules, Rules::BasicExpert);
+    for battle_num in 0..20 {
+        let battle = make_battle_order_list(&encounter.characters, encounter.rules, &Arc::new(Policies::default()));
+        let initiative_of = |team: Team| battle.battle_order_list.iter().filter(|order| order.team == team).map(|order| order.initative_roll).collect::<HashSet<i8>>();
+        let (heros, villains) = (initiative_of(Team::Heros), initiative_of(Team::Villains));
+        assert_eq!((heros.len(), villains.len()), (1, 1));
+        assert_ne!(heros, villains);
+        assert!(heros.iter().chain(&villains).all(|initiative| (1..=6).contains(initiative)));
+
+        let battle = new_battle(&encounter, &Arc::new(Policies::default())).run_battle(battle_num, &ReportOutputLevel::None);
+        assert!(battle.battle_order_list.iter().all(|order| order.character.hs2 != HealthState::Ko));
+        assert_eq!(battle.battle_result.crit_hits, 0);
+    }
+}
+
+#[test]
+fn pathfinder_test() {
+    use characterize::get_players;
+
+    let players = get_players();
+    let mut defender = players[0].clone();
+    defender.armour_class = 15;
+    let attack = |natural_roll: u8, attack_roll: u8| AttackResult {
+        attack_roll, natural_roll, other_roll: None, critical: natural_roll == 20, _roll_string: "d20".to_string(),
+    };
+    let pf2e = Rules::Pathfinder.ruleset();
+    let degrees = [(10, 25, ActionResultType::CritHit), (10, 24, ActionResultType::Hit), (10, 15, ActionResultType::Hit),
+        (10, 14, ActionResultType::Miss), (5, 6, ActionResultType::Miss), (5, 5, ActionResultType::CritMiss),
+        (20, 14, ActionResultType::Hit), (20, 5, ActionResultType::Miss), (1, 25, ActionResultType::Hit), (1, 15, ActionResultType::Miss)];
+    for (natural_roll, attack_roll, degree) in degrees {
+        assert_eq!(pf2e.attack_outcome(defender.armour_class, &attack(natural_roll, attack_roll)), degree, "{} on a natural {}", attack_roll, natural_roll);
+    }
+    assert_eq!((0..4).map(|made| pf2e.attack_penalty(made)).collect::<Vec<u8>>(), vec![0, 5, 10, 10]);
+    assert_eq!(pf2e.take_damage(HealthState::Alive(5), 5), HealthState::Ko);
+    assert_eq!(pf2e.take_damage(HealthState::Ko, 1), HealthState::Dead);
+
+    // only those with the feat make attacks of opportunity
+    let encounter = load_encounter("./input/cover.yaml", None).unwrap();
+    let mut battle = make_battle_order_list(&encounter.characters, Rules::Pathfinder, &Arc::new(Policies::default()));
+    battle.battlefield = encounter.battlefield.clone();
+    let mut combatants = battle.battle_order_list.clone();
+    let (fighter, crossbowman) = (index_of(&combatants, "Fighter"), index_of(&combatants, "Crossbowman"));
+    combatants[fighter].position = Some((2, 2));
+    combatants[crossbowman].position = Some((3, 2));
+    combatants[crossbowman].character.hs2 = HealthState::Alive(500);
+    let mut request_cache: RequestCache = Default::default();
+    let mut turn_result: TurnResult = Default::default();
+    battle.back_off(&mut combatants, crossbowman, 1, &mut request_cache, &mut turn_result);
+    assert!(turn_result.action_results.is_empty() && !combatants[fighter].reaction_used, "no Attack of Opportunity");
+    assert!(Rules::default().ruleset().opportunity_attacks(&combatants[fighter].character));
+
+    combatants[fighter].character.features.push("Attack of Opportunity".to_string());
+    combatants[crossbowman].position = Some((3, 2));
+    battle.back_off(&mut combatants, crossbowman, 1, &mut request_cache, &mut turn_result);
+    assert!(matches!(turn_result.action_results.last().unwrap().action_type, ActionType::OpportunityAttack));
+
+    // three strikes a turn, each after the first at a growing penalty
+    let encounter = load_encounter("./input/pathfinder.yaml", None).unwrap();
+    assert_eq!(encounter.rules, Rules::Pathfinder);
+    let battle = recorded_battle(&encounter, &Arc::new(Policies::default()), 0).unwrap();
+    let events = battle.event_log.events();
+    let mut strikes = 0;
+    let mut attacker = None;
+    for event in events {
+        if let Event::AttackRolled { attacker: striker, penalty, .. } = event {
+            // a first strike, opportunity attacks included, is made at no penalty
+            if *penalty == 0 {
+                strikes = 0;
+            }
+            else {
+                assert_eq!(attacker, Some(*striker), "penalties only follow the same attacker's strikes");
+            }
+            assert_eq!(*penalty, pf2e.attack_penalty(strikes));
+            strikes += 1;
+            assert!(strikes <= 3, "no more than three actions a turn");
+            attacker = Some(*striker);
+        }
+    }
+    assert!(events.iter().any(|event| matches!(event, Event::AttackRolled { penalty: 10, .. })));
+}
+
+#[test]
+fn cover_test() {
+    let encounter = load_encounter("./input/cover.yaml", None).unwrap();
+    let battlefield = encounter.battlefield.clone().unwrap();
+    let mut combatants = make_battle_order_list(&encounter.characters, Rules::default(), &Arc::new(Policies::default())).battle_order_list;
+    let (archer, crossbowman, goblin) = (index_of(&combatants, "Archer"), index_of(&combatants, "Crossbowman"), index_of(&combatants, "Goblin"));
+    for order in combatants.iter_mut() {
+        order.position = order.character.position;
+    }
+
+    // declared cover holds against everyone, but a wall can do better
+    assert_eq!(combatants[crossbowman].cover_from(combatants[archer].position, None), Some(Cover::ThreeQuarters));
+    assert_eq!(combatants[goblin].cover_from(Some((1, 4)), Some(&battlefield)), None);
+    combatants[goblin].position = Some((14, 4));
+    assert_eq!(combatants[goblin].cover_from(Some((10, 4)), Some(&battlefield)), Some(Cover::Total), "behind the wall");
+    combatants[archer].position = Some((10, 4));
+    let mut event_log = EventLog::recording();
+    let mut request_cache: RequestCache = Default::default();
+    let action_result = BattleOrderList::resolve_attack(&mut combatants, archer, goblin, Some(&battlefield), Rules::default().ruleset(), false, &mut request_cache, &mut event_log);
+    assert_eq!((action_result.action_result, action_result.cover), (ActionResultType::NoRoll, Some(Cover::Total)), "nothing to shoot at");
+    assert!(event_log.events().is_empty());
+
+    let mut battle = make_battle_order_list(&encounter.characters, Rules::default(), &Arc::new(Policies::default()));
+    let mut turn_result: TurnResult = Default::default();
+    battle.record_attack(&mut turn_result, action_result);
+    assert_eq!(battle.battle_result.covered_attacks, 0, "an attack never made isn't a covered one");
+
+    // nobody can lash out at a creature stepping away into total cover
+    battle.battlefield = encounter.battlefield.clone();
+    let fighter = index_of(&combatants, "Fighter");
+    combatants[fighter].position = Some((2, 2));
+    combatants[crossbowman].position = Some((3, 2));
+    combatants[crossbowman].character.hs2 = HealthState::Alive(500);
+    combatants[crossbowman].character.cover = Some(Cover::Total);
+    battle.back_off(&mut combatants, crossbowman, 1, &mut request_cache, &mut turn_result);
+    assert_eq!(distance(combatants[crossbowman].position.unwrap(), (2, 2)), 2, "out of the fighter's reach");
+    assert_eq!(turn_result.action_results.len(), 1, "no opportunity attack");
+    assert!(!combatants[fighter].reaction_used, "the reaction is kept for later");
+
+    combatants[crossbowman].position = Some((3, 2));
+    combatants[crossbowman].character.cover = Some(Cover::ThreeQuarters);
+    battle.back_off(&mut combatants, crossbowman, 1, &mut request_cache, &mut turn_result);
+    assert!(matches!(turn_result.action_results.last().unwrap().action_type, ActionType::OpportunityAttack));
+    assert!(combatants[fighter].reaction_used);
+
+    // nobody wastes their turns on an enemy that can't ever be attacked
+    let mut players = characterize::get_players();
+    players[0].hs2 = HealthState::Alive(500);
+    let mut battle = make_battle_order_list(&players, Rules::default(), &Arc::new(Policies::default()));
+    let hero = index_of(&battle.battle_order_list, "Hero");
+    let first_pick = battle.battle_order_list[hero].get_target(&battle.battle_order_list, Rules::default().ruleset()).unwrap();
+    battle.battle_order_list[first_pick].character.cover = Some(Cover::Total);
+    let view = BattleView { combatants: &battle.battle_order_list, actor: hero, ruleset: Rules::default().ruleset() };
+    assert!(!view.available_actions().contains(&Action::Attack(first_pick)));
+    let (battle, _) = battle.run_battle_turn(1, &mut request_cache);
+    let open = battle.battle_order_list[hero].current_target;
+    assert!(open.is_some_and(|open| open != first_pick), "the hero attacks the enemy in the open");
+
+    let battle = recorded_battle(&encounter, &Arc::new(Policies::default()), 0).unwrap();
+    let crossbowman = index_of(&battle.battle_order_list, "Crossbowman");
+    let mut covered = 0;
+    for event in battle.event_log.events() {
+        if let Event::AttackRolled { target, armour_class, cover, .. } = event {
+            if *target == crossbowman {
+                assert_eq!((*armour_class, *cover), (12 + 5, Some(Cover::ThreeQuarters)));
+            }
+            if let Some(cover) = cover {
+                assert_eq!(*armour_class, battle.battle_order_list[*target].character.armour_class + cover.bonus());
+                covered += 1;
+            }
+        }
+    }
+    assert!(covered > 0);
+    assert_eq!(battle.battle_result.covered_attacks, covered);
+}
+
+#[test]
+fn pack_tactics_test() {
+    use AdvantageSource::*;
+
+    let encounter = load_encounter("./input/pack_tactics.yaml", None).unwrap();
+    assert!(encounter.flanking);
+    let mut combatants = make_battle_order_list(&encounter.characters, Rules::default(), &Arc::new(Policies::default())).battle_order_list;
+    let (fighter, rogue, wolf, pack_mate) = (index_of(&combatants, "Fighter"), index_of(&combatants, "Rogue"), index_of(&combatants, "Wolf 1"), index_of(&combatants, "Wolf 2"));
+    assert!(combatants[wolf].character.features.contains(&"Pack Tactics".to_string()), "features come from the template");
+
+    // without a battlefield, a pack mate going after the same enemy is enough
+    assert!(combatants[wolf].advantage_sources(fighter, &combatants, true).is_empty());
+    combatants[pack_mate].current_target = Some(fighter);
+    assert_eq!(combatants[wolf].advantage_sources(fighter, &combatants, true), vec![PackTactics, Flanking]);
+    assert_eq!(combatants[rogue].advantage_sources(fighter, &combatants, true), vec![], "allies only");
+    combatants[pack_mate].character.hs2 = HealthState::Ko;
+    assert!(combatants[wolf].advantage_sources(fighter, &combatants, true).is_empty(), "not while the ally is down");
+    combatants[pack_mate].character.hs2 = HealthState::Alive(11);
+
+    // on a grid, the ally has to be next to the target, and on its far side to flank
+    for (order, square) in [(fighter, (3, 3)), (wolf, (2, 3)), (pack_mate, (4, 4)), (rogue, (0, 0))].iter().copied() {
+        combatants[order].position = Some(square);
+    }
+    for other in combatants.iter_mut().filter(|other| other.position.is_none()) {
+        other.position = Some((11, 7));
+    }
+    assert_eq!(combatants[wolf].advantage_sources(fighter, &combatants, true), vec![PackTactics]);
+    combatants[pack_mate].position = Some((4, 3));
+    assert_eq!(combatants[wolf].advantage_sources(fighter, &combatants, true), vec![PackTactics, Flanking]);
+    assert_eq!(combatants[wolf].advantage_sources(fighter, &combatants, false), vec![PackTactics], "flanking is optional");
+    combatants[pack_mate].position = Some((5, 3));
+    assert!(combatants[wolf].advantage_sources(fighter, &combatants, true).is_empty());
+
+    let battle = recorded_battle(&encounter, &Arc::new(Policies::default()), 0).unwrap();
+    let mut pack_attacks = 0;
+    for event in battle.event_log.events() {
+        if let Event::AttackRolled { attacker, sources, .. } = event {
+            let attacker = &battle.battle_order_list[*attacker].character;
+            assert!(!sources.contains(&PackTactics) || attacker.features.contains(&"Pack Tactics".to_string()));
+            if sources.contains(&PackTactics) {
+                pack_attacks += 1;
+            }
+        }
+    }
+    assert!(pack_attacks > 0, "three wolves should gang up on someone");
+}
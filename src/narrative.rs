@@ -0,0 +1,209 @@
+use crate::battlefield::Cover;
+use crate::characterize::HealthState;
+use crate::event_log::{BattleState, Condition, Event};
+
+/// Tells a recorded battle as play-by-play prose, one line per action:
+/// an attack, its damage and anyone it knocked out go together.
+pub fn narrate(events: &[Event]) -> Vec<String> {
+    let mut state = BattleState::default();
+    let mut lines: Vec<String> = Vec::new();
+    let mut previous: Option<&Event> = None;
+
+    for event in events {
+        let round = if state.round > 0 { format!("Round {}: ", state.round) } else { String::new() };
+        let name = |combatant: &usize| state.combatants.get(*combatant).map(|state| state.name.clone()).unwrap_or_default();
+        match event {
+            Event::Joined { name, team, health, .. } if state.round > 0 =>
+                lines.push(format!("{}{} joins the {} with {}", round, name, team, hit_points(*health))),
+            Event::Joined { .. } => (),
+            Event::InitiativeRolled { initiative, .. } if state.round > 0 => append(&mut lines, &format!(" and initiative {}.", initiative)),
+            Event::InitiativeRolled { combatant, initiative, .. } => match lines.last() {
+                Some(line) if line.starts_with("Initiative: ") => append(&mut lines, &format!(", {} {}", name(combatant), initiative)),
+                _ => lines.push(format!("Initiative: {} {}", name(combatant), initiative)),
+            },
+            Event::RoundStarted { .. } => (),
+            Event::Moved { combatant, to, .. } => {
+                let line = format!("{}{} moves to {:?}.", round, name(combatant), to);
+                match previous {
+                    Some(Event::Moved { combatant: mover, .. }) if mover == combatant => *lines.last_mut().unwrap() = line,
+                    _ => lines.push(line),
+                }
+            }
+            Event::AttackRolled { attacker, target, kept, penalty, total, armour_class, critical, hit, sources, cover, opportunity, .. } => {
+                // the dice can come up level, so only the sources tell which it was
+                let mode = match (sources.iter().any(|source| source.is_advantage()), sources.iter().any(|source| !source.is_advantage())) {
+                    (true, false) => " with advantage",
+                    (false, true) => " with disadvantage",
+                    (true, true) => " with advantage and disadvantage cancelling out",
+                    (false, false) => "",
+                };
+                let mode = match sources.as_slice() {
+                    [] => mode.to_string(),
+                    _ => format!("{} ({})", mode, sources.iter().map(|source| source.to_string()).collect::<Vec<String>>().join(", ")),
+                };
+                let result = match (critical, hit) {
+                    (true, _) => "lands a critical hit",
+                    (false, true) => "hits",
+                    (false, false) if *kept == 1 => "fumbles",
+                    (false, false) => "misses",
+                };
+                let (swings, passing) = if *opportunity { ("lashes out at", " as they move away") } else { ("swings at", "") };
+                let penalty = if *penalty > 0 { format!(" after a -{} penalty", penalty) } else { String::new() };
+                lines.push(format!("{}{} {} {}{} (rolls {}{}{} vs AC {}{}) and {}", round, name(attacker), swings, name(target), passing, total, mode, penalty, armour_class, behind(cover), result));
+                if !hit {
+                    append(&mut lines, ".");
+                }
+            }
+            Event::AreaAttack { attacker, name: attack, damage } =>
+                lines.push(format!("{}{} unleashes {} for {} damage.", round, name(attacker), attack, damage)),
+            Event::SavingThrow { combatant, total, dc, saved, cover, .. } =>
+                lines.push(format!("{}{} {} the save ({}{} vs DC {})", round, name(combatant), if *saved { "makes" } else { "fails" }, total, behind(cover), dc)),
+            Event::DamageApplied { target, amount, health } => {
+                let aftermath = match health {
+                    HealthState::Alive(_) => format!("; {} is down to {}.", name(target), hit_points(*health)),
+                    // going down is told by the state change that follows
+                    _ if state.combatants.get(*target).is_some_and(|state| state.health != *health) => String::new(),
+                    _ => format!("; {} stays down.", name(target)),
+                };
+                match previous {
+                    Some(Event::AttackRolled { .. }) => append(&mut lines, &format!(" for {} damage{}", amount, aftermath)),
+                    Some(Event::SavingThrow { .. }) => append(&mut lines, &format!(" and takes {} damage{}", amount, aftermath)),
+                    _ => lines.push(format!("{}{} takes {} damage{}", round, name(target), amount, aftermath)),
+                }
+            }
+            Event::Healed { healer, target, name: heal, amount, health } => {
+                let on = if healer == target { String::new() } else { format!(" on {}", name(target)) };
+                // getting back up is told by the state change that follows
+                let aftermath = match state.combatants.get(*target) {
+                    Some(state) if state.health == HealthState::Ko => String::new(),
+                    _ => format!("; {} is up to {}.", name(target), hit_points(*health)),
+                };
+                lines.push(format!("{}{} uses {}{}, restoring {} HP{}", round, name(healer), heal, on, amount, aftermath));
+            }
+            Event::StateChanged { combatant, to, .. } => {
+                let notice = match to {
+                    HealthState::Ko => "is knocked out!",
+                    HealthState::Dead => "dies!",
+                    HealthState::Alive(_) => "is back on their feet.",
+                };
+                match previous {
+                    Some(Event::DamageApplied { .. } | Event::Healed { .. }) => append(&mut lines, &format!("; {} {}", name(combatant), notice)),
+                    _ => lines.push(format!("{}{} {}", round, name(combatant), notice)),
+                }
+            }
+            // being knocked out already says they're down
+            Event::ConditionApplied { condition: Condition::Prone, .. } if matches!(previous, Some(Event::StateChanged { .. })) => (),
+            Event::ConditionApplied { combatant, condition } => {
+                let doing = match condition {
+                    Condition::Dodging => Some("takes the Dodge action."),
+                    Condition::Hidden => Some("slips out of sight."),
+                    Condition::Helped => Some("gets a helping hand."),
+                    Condition::Disengaged => Some("disengages."),
+                    Condition::Fled => Some("flees the battle!"),
+                    Condition::Surrendered => Some("surrenders!"),
+                    Condition::Prone => Some("falls prone."),
+                    // told by the attacks it goes with
+                    Condition::Reckless => None,
+                    Condition::Outlined => Some("is outlined by faerie fire."),
+                };
+                if let Some(doing) = doing {
+                    lines.push(format!("{}{} {}", round, name(combatant), doing));
+                }
+            }
+            Event::ConditionEnded { .. } => (),
+            Event::BattleEnded { rounds, outcome, .. } =>
+                lines.push(format!("Battle over after {} rounds: {}.", rounds, outcome)),
+        }
+        // anyone the log never introduced simply goes unnamed
+        let _ = state.apply(event);
+        previous = Some(event);
+    }
+    lines
+}
+
+fn hit_points(health: HealthState) -> String {
+    match health {
+        HealthState::Alive(hit_points) => format!("{} HP", hit_points),
+        HealthState::Ko => "0 HP".to_string(),
+        HealthState::Dead => "no HP".to_string(),
+    }
+}
+
+fn behind(cover: &Option<Cover>) -> String {
+    match cover {
+        Some(cover) => format!(" behind {} cover", cover),
+        None => String::new(),
+    }
+}
+
+fn append(lines: &mut [String], text: &str) {
+    if let Some(line) = lines.last_mut() {
+        line.push_str(text);
+    }
+}
+
+#[test]
+fn narrate_test() {
+    use crate::characterize::Team;
+    use crate::event_log::AdvantageSource;
+
+    let joined = |combatant: usize, name: &str, team: Team, hit_points: u16| Event::Joined {
+        combatant, name: name.to_string(), team, health: HealthState::Alive(hit_points), position: None,
+    };
+    let events = vec![
+        joined(0, "Villan-A", Team::Villains, 7),
+        Event::InitiativeRolled { combatant: 0, roll: 15, modifier: 2, initiative: 17 },
+        joined(1, "Hero", Team::Heros, 7),
+        Event::InitiativeRolled { combatant: 1, roll: 4, modifier: 0, initiative: 4 },
+        Event::RoundStarted { round: 2 },
+        Event::AttackRolled { attacker: 0, target: 1, rolls: vec![12], kept: 12, bonus: 2, penalty: 0, total: 14, armour_class: 12, critical: false, hit: true, sources: vec![], cover: None, opportunity: false },
+        Event::DamageApplied { target: 1, amount: 3, health: HealthState::Alive(4) },
+        Event::Moved { combatant: 1, from: (0, 0), to: (0, 1) },
+        Event::Moved { combatant: 1, from: (0, 1), to: (0, 2) },
+        Event::AttackRolled { attacker: 0, target: 1, rolls: vec![1], kept: 1, bonus: 2, penalty: 0, total: 3, armour_class: 12, critical: false, hit: false, sources: vec![], cover: None, opportunity: true },
+        Event::ConditionApplied { combatant: 0, condition: Condition::Dodging },
+        Event::AttackRolled { attacker: 1, target: 0, rolls: vec![3, 9], kept: 3, bonus: 0, penalty: 0, total: 3, armour_class: 14, critical: false, hit: false, sources: vec![AdvantageSource::TargetDodging], cover: Some(Cover::Half), opportunity: false },
+        Event::Healed { healer: 1, target: 1, name: "Second Wind".to_string(), amount: 3, health: HealthState::Alive(7) },
+        Event::AttackRolled { attacker: 0, target: 1, rolls: vec![20], kept: 20, bonus: 2, penalty: 0, total: 22, armour_class: 12, critical: true, hit: true, sources: vec![AdvantageSource::Helped, AdvantageSource::LongRange], cover: None, opportunity: false },
+        Event::DamageApplied { target: 1, amount: 9, health: HealthState::Ko },
+        Event::StateChanged { combatant: 1, from: HealthState::Alive(7), to: HealthState::Ko },
+        Event::ConditionApplied { combatant: 1, condition: Condition::Prone },
+        Event::BattleEnded { rounds: 2, outcome: "Villans".to_string(), winner: Some(Team::Villains) },
+    ];
+
+    assert_eq!(narrate(&events), vec![
+        "Initiative: Villan-A 17, Hero 4",
+        "Round 2: Villan-A swings at Hero (rolls 14 vs AC 12) and hits for 3 damage; Hero is down to 4 HP.",
+        "Round 2: Hero moves to (0, 2).",
+        "Round 2: Villan-A lashes out at Hero as they move away (rolls 3 vs AC 12) and fumbles.",
+        "Round 2: Villan-A takes the Dodge action.",
+        "Round 2: Hero swings at Villan-A (rolls 3 with disadvantage (target dodging) vs AC 14 behind half cover) and misses.",
+        "Round 2: Hero uses Second Wind, restoring 3 HP; Hero is up to 7 HP.",
+        "Round 2: Villan-A swings at Hero (rolls 22 with advantage and disadvantage cancelling out (helped, long range) vs AC 12) and lands a critical hit for 9 damage; Hero is knocked out!",
+        "Battle over after 2 rounds: Villans.",
+    ]);
+
+    // walking up to an enemy and getting hit on its turn is no opportunity attack
+    let walked_up = vec![
+        joined(0, "Villan-A", Team::Villains, 7),
+        joined(1, "Hero", Team::Heros, 7),
+        Event::RoundStarted { round: 1 },
+        Event::Moved { combatant: 1, from: (0, 2), to: (0, 1) },
+        Event::ConditionApplied { combatant: 0, condition: Condition::Reckless },
+        Event::AttackRolled { attacker: 0, target: 1, rolls: vec![8], kept: 8, bonus: 2, penalty: 0, total: 10, armour_class: 12, critical: false, hit: false, sources: vec![], cover: None, opportunity: false },
+    ];
+    assert_eq!(narrate(&walked_up), vec![
+        "Round 1: Hero moves to (0, 1).",
+        "Round 1: Villan-A swings at Hero (rolls 10 vs AC 12) and misses.",
+    ], "a reckless attack needs no line of its own");
+
+    let level_dice = vec![
+        joined(0, "Villan-A", Team::Villains, 7),
+        joined(1, "Hero", Team::Heros, 7),
+        Event::RoundStarted { round: 1 },
+        Event::AttackRolled { attacker: 0, target: 1, rolls: vec![12, 12], kept: 12, bonus: 2, penalty: 0, total: 14, armour_class: 15, critical: false, hit: false, sources: vec![AdvantageSource::Threatened], cover: None, opportunity: false },
+    ];
+    assert_eq!(narrate(&level_dice), vec![
+        "Round 1: Villan-A swings at Hero (rolls 14 with disadvantage (enemy adjacent) vs AC 15) and misses.",
+    ], "equal dice don't make disadvantage into advantage");
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use crate::BattleOrder;
+use crate::characterize::{CharacterStruct, HealPriority, HealthState};
+use crate::ruleset::Ruleset;
+
+const DEFAULT_POLICY: &str = "RuleBased";
+
+/// What a combatant does with its turn. Targets are indexes into the
+/// battle order, as handed to the policy in its `BattleView`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Attack(usize),
+    /// The actor's first area attack with uses left, aimed at the target.
+    Cast(usize),
+    Dodge,
+    Dash,
+    Disengage,
+    Hide,
+    Help(usize),
+    /// The actor's first heal with uses left that works on the target.
+    Heal(usize),
+    /// Leaves the battle; on a battlefield it runs first, risking opportunity attacks.
+    Flee,
+    Surrender,
+    /// The actor's first summons with uses left.
+    Summon,
+    Pass,
+}
+
+/// A read-only look at the battle from the point of view of the combatant
+/// whose turn it is, under the battle's ruleset.
+pub struct BattleView<'a> {
+    pub combatants: &'a [BattleOrder],
+    pub actor: usize,
+    pub ruleset: &'a dyn Ruleset,
+}
+
+impl<'a> BattleView<'a> {
+    pub fn actor(&self) -> &'a CharacterStruct {
+        &self.combatants[self.actor].character
+    }
+
+    /// Conscious enemies, only those within reach this turn if any are.
+    pub fn enemies(&self) -> impl Iterator<Item = (usize, &'a BattleOrder)> {
+        crate::targeting::enemies(&self.combatants[self.actor], self.combatants)
+    }
+
+    pub fn allies(&self) -> impl Iterator<Item = (usize, &'a BattleOrder)> + '_ {
+        let team = self.actor().team;
+        let actor = self.actor;
+        self.combatants.iter().enumerate()
+            .filter(move |(i, other)| *i != actor && other.character.team == team && other.is_fighting())
+    }
+
+    /// Teammates, the actor included, who are hurt or down but neither dead
+    /// nor withdrawn, and whom one of the actor's heals could help.
+    pub fn wounded(&self) -> impl Iterator<Item = (usize, &'a BattleOrder)> + '_ {
+        let actor = &self.combatants[self.actor];
+        self.combatants.iter().enumerate()
+            .filter(move |(i, other)| other.team == actor.team && other.withdrawn.is_none()
+                && other.character.hs2 != HealthState::Dead && other.character.hit_points() < other.max_hp
+                && actor.character.usable_heal(*i == self.actor).is_some())
+    }
+
+    /// Who the actor would spend its turn healing, going by its
+    /// `heal_priority`: allies who are down first, most hurt first.
+    pub fn patient(&self) -> Option<usize> {
+        let threshold = match self.actor().heal_priority {
+            HealPriority::Never => return None,
+            HealPriority::DownedFirst => 0,
+            HealPriority::Below { percent } => percent as u32,
+        };
+        let percent_left = |order: &BattleOrder| order.character.hit_points() as u32 * 100 / order.max_hp.max(1) as u32;
+        self.wounded()
+            .filter(|(_, order)| order.character.hs2 == HealthState::Ko || percent_left(order) < threshold)
+            .min_by_key(|(_, order)| (order.character.hs2 != HealthState::Ko, percent_left(order)))
+            .map(|(i, _)| i)
+    }
+
+    /// Whether any creature the actor summoned is still in the fight.
+    pub fn summons_fighting(&self) -> bool {
+        self.combatants.iter().any(|other| other.summoned_by == Some(self.actor) && other.is_fighting())
+    }
+
+    /// Every action open to the actor this turn.
+    pub fn available_actions(&self) -> Vec<Action> {
+        let mut actions: Vec<Action> = self.enemies().map(|(i, _)| Action::Attack(i)).collect();
+        if self.actor().usable_area_attack().is_some() {
+            actions.extend(self.enemies().map(|(i, _)| Action::Cast(i)));
+        }
+        actions.extend(self.allies().map(|(i, _)| Action::Help(i)));
+        actions.extend(self.wounded().map(|(i, _)| Action::Heal(i)));
+        if self.actor().usable_summon().is_some() {
+            actions.push(Action::Summon);
+        }
+        actions.extend([Action::Dodge, Action::Dash, Action::Disengage, Action::Hide, Action::Flee, Action::Surrender]);
+        actions
+    }
+
+    /// Whether the actor's area attack aimed at `target` can be brought within
+    /// reach this turn and would catch at least two enemies and nobody on its
+    /// own side.
+    pub fn worth_an_area_attack(&self, target: usize) -> bool {
+        let actor = &self.combatants[self.actor];
+        let area = match actor.character.usable_area_attack() {
+            Some(area) => &actor.character.area_attacks[area],
+            None => return false,
+        };
+        let reach = area.shape.reach(area.range);
+        if actor.distance_to(&self.combatants[target]).is_some_and(|gap| gap > actor.character.speed().saturating_add(reach)) {
+            return false;
+        }
+        let caught = actor.area_targets(area, target, self.combatants);
+        caught.len() >= 2 && caught.iter().all(|caught| self.combatants[*caught].team != actor.team)
+    }
+
+    /// Chance to hit times average damage, capped at what the target has left.
+    pub fn expected_damage(&self, target: usize) -> f32 {
+        let attacker = &self.combatants[self.actor];
+        let target = &self.combatants[target].character;
+        let remaining = match target.hs2 {
+            HealthState::Alive(hit_points) => hit_points as f32,
+            HealthState::Ko | HealthState::Dead => 0.0,
+        };
+        let average_damage = (attacker.character.damage as f32 + 1.0) / 2.0;
+        (attacker.hit_chance(target, self.ruleset) * average_damage).min(remaining)
+    }
+}
+
+/// Decides what a combatant does on its turn.
+pub trait CombatPolicy {
+    fn choose_action(&self, view: &BattleView) -> Action;
+}
+
+/// Heals whoever the character's heal priority calls for first. Otherwise
+/// goes after whoever its targeting strategy picks, with an area attack if
+/// that catches several enemies and no allies. Summoners call up help while
+/// they still can and none of what they called up is still fighting.
+pub struct RuleBased;
+
+impl CombatPolicy for RuleBased {
+    fn choose_action(&self, view: &BattleView) -> Action {
+        if let Some(patient) = view.patient() {
+            return Action::Heal(patient);
+        }
+        match view.combatants[view.actor].get_target(view.combatants, view.ruleset) {
+            Some(target) if view.worth_an_area_attack(target) => Action::Cast(target),
+            Some(_) if view.actor().usable_summon().is_some() && !view.summons_fighting() => Action::Summon,
+            Some(target) => Action::Attack(target),
+            None => Action::Pass,
+        }
+    }
+}
+
+/// Attacks whichever enemy it expects to take the most hit points from,
+/// preferring the weaker target when that is a tie.
+pub struct GreedyDamage;
+
+impl CombatPolicy for GreedyDamage {
+    fn choose_action(&self, view: &BattleView) -> Action {
+        view.available_actions().into_iter()
+            .filter_map(|action| match action {
+                Action::Attack(target) => Some((target, view.expected_damage(target))),
+                _ => None,
+            })
+            .max_by(|(a, a_damage), (b, b_damage)| a_damage.partial_cmp(b_damage).unwrap()
+                .then_with(|| view.combatants[*b].character.hs2.cmp(&view.combatants[*a].character.hs2)))
+            .map_or(Action::Pass, |(target, _)| Action::Attack(target))
+    }
+}
+
+/// The policies characters can name with `policy`. Register your own before
+/// the battles start to have characters in the input file use it.
+#[derive(Clone)]
+pub struct Policies {
+    by_name: HashMap<String, Arc<dyn CombatPolicy + Send + Sync>>,
+}
+
+impl Default for Policies {
+    fn default() -> Self {
+        let mut policies = Policies { by_name: HashMap::new() };
+        policies.register(DEFAULT_POLICY, Arc::new(RuleBased));
+        policies.register("GreedyDamage", Arc::new(GreedyDamage));
+        policies
+    }
+}
+
+impl fmt::Debug for Policies {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut names: Vec<&String> = self.by_name.keys().collect();
+        names.sort();
+        write!(f, "Policies{:?}", names)
+    }
+}
+
+impl Policies {
+    pub fn register(&mut self, name: &str, policy: Arc<dyn CombatPolicy + Send + Sync>) {
+        self.by_name.insert(name.to_string(), policy);
+    }
+
+    /// The character's policy, or the rule based default if it names none.
+    pub fn for_character(&self, character: &CharacterStruct) -> &dyn CombatPolicy {
+        let name = character.policy.as_deref().unwrap_or(DEFAULT_POLICY);
+        match self.by_name.get(name) {
+            Some(policy) => policy.as_ref(),
+            None => self.by_name[DEFAULT_POLICY].as_ref(),
+        }
+    }
+
+    pub fn check<'a>(&self, players: impl IntoIterator<Item = &'a CharacterStruct>) -> Result<(), String> {
+        for player in players {
+            if let Some(name) = &player.policy {
+                if !self.by_name.contains_key(name) {
+                    return Err(format!("{} uses unknown policy {}", player.name, name));
+                }
+            }
+        }
+        Ok(())
+    }
+}
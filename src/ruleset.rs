@@ -0,0 +1,248 @@
+use serde::{Deserialize};
+use std::fmt;
+use std::str::FromStr;
+
+use crate::characterize::{CharacterStruct, HealthState};
+use crate::dice_thrower;
+use crate::{ActionResultType, AttackResult, BattleOrder, BattleOutcome, RequestCache};
+
+/// The game system's answers to how a battle plays out: who acts first,
+/// whether an attack lands, how hard it hits, what damage does to a creature
+/// and when a side has won. Tactics, movement and morale stay outside it.
+pub trait Ruleset {
+    fn initiative_die(&self) -> &'static str {
+        "1d20"
+    }
+
+    fn initiative_modifier(&self, character: &CharacterStruct) -> i8 {
+        character.dexterity_modifier
+    }
+
+    /// `character`'s initiative, knowing everyone who has `entered` so far.
+    fn initiative(&self, character: &CharacterStruct, _entered: &[BattleOrder], request_cache: &mut RequestCache) -> i8 {
+        let request = request_cache.get_roll_request(self.initiative_die());
+        dice_thrower::throw_roll(&request) as i8 + self.initiative_modifier(character)
+    }
+
+    /// How `attack` fares against `armour_class`, cover included.
+    fn attack_outcome(&self, armour_class: u8, attack: &AttackResult) -> ActionResultType;
+
+    /// Taken off an attack roll for the attacks already made this turn.
+    fn attack_penalty(&self, _attacks_made: u8) -> u8 {
+        0
+    }
+
+    /// Actions a creature gets each turn, moving and attacking alike, or None
+    /// when it may move and make all its attacks.
+    fn actions_per_turn(&self) -> Option<u8> {
+        None
+    }
+
+    /// Whether `character` can spend its reaction attacking a creature that
+    /// leaves its reach.
+    fn opportunity_attacks(&self, _character: &CharacterStruct) -> bool {
+        true
+    }
+
+    fn damage(&self, attacker: &CharacterStruct, critical: bool, request_cache: &mut RequestCache) -> u16 {
+        let request = request_cache.get_roll_request(
+            &format!("d{}", attacker.damage));       // lexer needs a fix, till then prepend d
+        if critical { dice_thrower::throw_critical_roll(&request) } else { dice_thrower::throw_roll(&request) }
+    }
+
+    fn take_damage(&self, health: HealthState, damage: u16) -> HealthState;
+
+    /// How the battle ended if only one side, or nobody, is left fighting.
+    /// Losers who fled or surrendered rather than fell make it a rout.
+    fn victory(&self, combatants: &[BattleOrder]) -> Option<BattleOutcome> {
+        let mut standing = combatants.iter().filter(|order| order.is_fighting());
+        let winner = match standing.next() {
+            Some(winner) => winner.team,
+            None => return Some(BattleOutcome::Draw),
+        };
+        if standing.any(|order| order.team != winner) {
+            return None;
+        }
+        let routed = combatants.iter()
+            .any(|loser| loser.team != winner && loser.withdrawn.is_some() && loser.character.is_concious());
+        Some(if routed { BattleOutcome::Rout(winner) } else { BattleOutcome::Victory(winner) })
+    }
+}
+
+/// The built in rulesets, selectable per roster with `ruleset`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Rules {
+    #[default]
+    FifthEdition,
+    /// Old school Basic/Expert: side initiative on a d6, no criticals and
+    /// death at zero hit points.
+    BasicExpert,
+    /// Pathfinder second edition: four degrees of success, the multiple
+    /// attack penalty and three actions a turn.
+    Pathfinder,
+}
+
+impl Rules {
+    pub fn ruleset(&self) -> &'static dyn Ruleset {
+        match self {
+            Rules::FifthEdition => &FifthEdition,
+            Rules::BasicExpert => &BasicExpert,
+            Rules::Pathfinder => &Pathfinder,
+        }
+    }
+}
+
+impl FromStr for Rules {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "FifthEdition" => Ok(Rules::FifthEdition),
+            "BasicExpert" => Ok(Rules::BasicExpert),
+            "Pathfinder" => Ok(Rules::Pathfinder),
+            _ => Err(format!("Unknown ruleset: {}", name)),
+        }
+    }
+}
+
+impl fmt::Display for Rules {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+pub struct FifthEdition;
+
+impl Ruleset for FifthEdition {
+    /// A natural 1 always misses and a critical always hits; otherwise the
+    /// attack has to meet or beat armour class.
+    fn attack_outcome(&self, armour_class: u8, attack: &AttackResult) -> ActionResultType {
+        if attack.natural_roll == 1 {
+            ActionResultType::CritMiss
+        }
+        else if attack.critical {
+            ActionResultType::CritHit
+        }
+        else if attack.attack_roll >= armour_class {
+            ActionResultType::Hit
+        }
+        else { ActionResultType::Miss }
+    }
+
+    /// Dropping to exactly zero knocks a creature out and going below kills
+    /// it; once down, a big enough blow finishes it off.
+    fn take_damage(&self, health: HealthState, damage: u16) -> HealthState {
+        const KO_THRESHOLD: i16 = -10;
+        let damage = damage as i16;
+
+        match health {
+            HealthState::Dead => HealthState::Dead,
+            HealthState::Ko => if (0 - damage) < KO_THRESHOLD { HealthState::Dead } else { HealthState::Ko },
+            HealthState::Alive(hit_points) => match hit_points as i16 - damage {
+                x if x < 0 => HealthState::Dead,
+                0 => HealthState::Ko,
+                left => HealthState::Alive(left as u16),
+            },
+        }
+    }
+}
+
+pub struct BasicExpert;
+
+impl Ruleset for BasicExpert {
+    fn initiative_die(&self) -> &'static str {
+        "1d6"
+    }
+
+    fn initiative_modifier(&self, _character: &CharacterStruct) -> i8 {
+        0
+    }
+
+    /// Each side rolls once and everyone on it acts together. Ties are
+    /// rerolled so the sides never interleave.
+    fn initiative(&self, character: &CharacterStruct, entered: &[BattleOrder], request_cache: &mut RequestCache) -> i8 {
+        if let Some(side) = entered.iter().find(|order| order.team == character.team) {
+            return side.initative_roll;
+        }
+        let request = request_cache.get_roll_request(self.initiative_die());
+        loop {
+            let roll = dice_thrower::throw_roll(&request) as i8;
+            if !entered.iter().any(|order| order.initative_roll == roll) {
+                return roll;
+            }
+        }
+    }
+
+    /// A natural 20 always hits and a natural 1 always misses, with nothing
+    /// extra for either.
+    fn attack_outcome(&self, armour_class: u8, attack: &AttackResult) -> ActionResultType {
+        match attack.natural_roll {
+            1 => ActionResultType::Miss,
+            20 => ActionResultType::Hit,
+            _ if attack.attack_roll >= armour_class => ActionResultType::Hit,
+            _ => ActionResultType::Miss,
+        }
+    }
+
+    fn take_damage(&self, health: HealthState, damage: u16) -> HealthState {
+        match health {
+            HealthState::Alive(hit_points) if hit_points > damage => HealthState::Alive(hit_points - damage),
+            _ => HealthState::Dead,
+        }
+    }
+}
+
+pub struct Pathfinder;
+
+impl Ruleset for Pathfinder {
+    /// Beating armour class by 10 is a critical success and missing it by 10
+    /// a critical failure; a natural 20 or 1 then moves the result one degree
+    /// up or down.
+    fn attack_outcome(&self, armour_class: u8, attack: &AttackResult) -> ActionResultType {
+        const DEGREES: [ActionResultType; 4] = [ActionResultType::CritMiss, ActionResultType::Miss, ActionResultType::Hit, ActionResultType::CritHit];
+        let margin = attack.attack_roll as i16 - armour_class as i16;
+        let degree: usize = match margin {
+            m if m >= 10 => 3,
+            m if m >= 0 => 2,
+            m if m > -10 => 1,
+            _ => 0,
+        };
+        match attack.natural_roll {
+            20 => DEGREES[(degree + 1).min(3)],
+            1 => DEGREES[degree.saturating_sub(1)],
+            _ => DEGREES[degree],
+        }
+    }
+
+    /// The multiple attack penalty: -5 on the second attack and -10 after.
+    fn attack_penalty(&self, attacks_made: u8) -> u8 {
+        5 * attacks_made.min(2)
+    }
+
+    fn actions_per_turn(&self) -> Option<u8> {
+        Some(3)
+    }
+
+    /// Attack of Opportunity is a feat, not a reaction everyone has.
+    fn opportunity_attacks(&self, character: &CharacterStruct) -> bool {
+        character.features.iter().any(|feature| feature == "Attack of Opportunity")
+    }
+
+    /// A critical success doubles all the damage, modifiers included.
+    fn damage(&self, attacker: &CharacterStruct, critical: bool, request_cache: &mut RequestCache) -> u16 {
+        let request = request_cache.get_roll_request(&format!("d{}", attacker.damage));
+        let damage = dice_thrower::throw_roll(&request);
+        if critical { damage * 2 } else { damage }
+    }
+
+    /// Dropping to 0 knocks a creature out rather than killing it; dying
+    /// creatures are finished off by any further damage.
+    fn take_damage(&self, health: HealthState, damage: u16) -> HealthState {
+        match health {
+            HealthState::Alive(hit_points) if hit_points > damage => HealthState::Alive(hit_points - damage),
+            HealthState::Alive(_) => HealthState::Ko,
+            _ if damage > 0 => HealthState::Dead,
+            other => other,
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use serde::{Deserialize};
+use std::fmt;
+use std::str::FromStr;
+use rand::Rng;
+
+use crate::BattleOrder;
+use crate::battlefield::Cover;
+use crate::characterize::{CharacterStruct, HealthState, Team};
+use crate::ruleset::Ruleset;
+
+/// Picks which combatant in `combatants` the attacker goes after, if any,
+/// under the battle's `ruleset`. On a battlefield, enemies the attacker can
+/// reach this turn come first.
+pub trait TargetingStrategy {
+    fn select_target(&self, attacker: &BattleOrder, combatants: &[BattleOrder], ruleset: &dyn Ruleset) -> Option<usize>;
+}
+
+/// The built in strategies, selectable per character with `targeting`.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum Targeting {
+    /// The first conscious enemy in initiative order.
+    #[default]
+    First,
+    Random,
+    LowestHp,
+    HighestThreat,
+    LowestArmourClass,
+    /// Whichever enemy allies are already attacking the most.
+    FocusFire,
+    /// Whichever enemy allies are attacking the least.
+    SpreadDamage,
+    /// Enemies knocked out but not yet dead come first, if a hit could kill them.
+    FinishOffDowned,
+}
+
+impl Targeting {
+    pub fn strategy(&self) -> &'static dyn TargetingStrategy {
+        match self {
+            Targeting::First => &First,
+            Targeting::Random => &RandomTarget,
+            Targeting::LowestHp => &LowestHp,
+            Targeting::HighestThreat => &HighestThreat,
+            Targeting::LowestArmourClass => &LowestArmourClass,
+            Targeting::FocusFire => &FocusFire,
+            Targeting::SpreadDamage => &SpreadDamage,
+            Targeting::FinishOffDowned => &FinishOffDowned,
+        }
+    }
+}
+
+impl FromStr for Targeting {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "First" => Ok(Targeting::First),
+            "Random" => Ok(Targeting::Random),
+            "LowestHp" => Ok(Targeting::LowestHp),
+            "HighestThreat" => Ok(Targeting::HighestThreat),
+            "LowestArmourClass" => Ok(Targeting::LowestArmourClass),
+            "FocusFire" => Ok(Targeting::FocusFire),
+            "SpreadDamage" => Ok(Targeting::SpreadDamage),
+            "FinishOffDowned" => Ok(Targeting::FinishOffDowned),
+            _ => Err(format!("Unknown targeting strategy: {}", name)),
+        }
+    }
+}
+
+impl fmt::Display for Targeting {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+/// Describes the strategies in play, e.g. `Heros:FocusFire Villains:First`,
+/// so results from runs with different strategies can be told apart.
+pub fn targeting_label(players: &[CharacterStruct]) -> String {
+    let mut pairs: Vec<(Team, Targeting)> = players.iter().map(|p| (p.team, p.targeting)).collect();
+    pairs.sort();
+    pairs.dedup();
+    pairs.iter()
+        .map(|(team, targeting)| format!("{}:{}", team, targeting))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Conscious enemies, narrowed to those within reach this turn unless none are,
+/// in which case the attacker picks one to close in on. Enemies always in
+/// total cover can never be attacked, so are left out; a wall can be walked
+/// around.
+pub fn enemies<'a>(attacker: &'a BattleOrder, combatants: &'a [BattleOrder]) -> impl Iterator<Item = (usize, &'a BattleOrder)> {
+    let standing = move |target: &BattleOrder| target.team != attacker.team && target.is_fighting()
+        && target.cover_from(attacker.position, None) != Some(Cover::Total);
+    let any_in_reach = combatants.iter().any(|target| standing(target) && attacker.within_reach_this_turn(target));
+    combatants.iter().enumerate()
+        .filter(move |(_, target)| standing(target) && (!any_in_reach || attacker.within_reach_this_turn(target)))
+}
+
+/// How many of the attacker's allies currently have `target` as their target,
+/// not counting the attacker's own.
+fn ally_pressure(attacker: &BattleOrder, combatants: &[BattleOrder], target: usize) -> usize {
+    combatants.iter()
+        .filter(|ally| ally.team == attacker.team && ally.current_target == Some(target))
+        .count()
+        .saturating_sub(usize::from(attacker.current_target == Some(target)))
+}
+
+struct First;
+
+impl TargetingStrategy for First {
+    fn select_target(&self, attacker: &BattleOrder, combatants: &[BattleOrder], _ruleset: &dyn Ruleset) -> Option<usize> {
+        enemies(attacker, combatants).map(|(i, _)| i).next()
+    }
+}
+
+struct RandomTarget;
+
+impl TargetingStrategy for RandomTarget {
+    fn select_target(&self, attacker: &BattleOrder, combatants: &[BattleOrder], _ruleset: &dyn Ruleset) -> Option<usize> {
+        let candidates: Vec<usize> = enemies(attacker, combatants).map(|(i, _)| i).collect();
+        if candidates.is_empty() {
+            return None;
+        }
+        Some(candidates[rand::thread_rng().gen_range(0..candidates.len())])
+    }
+}
+
+struct LowestHp;
+
+impl TargetingStrategy for LowestHp {
+    fn select_target(&self, attacker: &BattleOrder, combatants: &[BattleOrder], _ruleset: &dyn Ruleset) -> Option<usize> {
+        enemies(attacker, combatants)
+            .min_by_key(|(_, target)| target.character.hit_points())
+            .map(|(i, _)| i)
+    }
+}
+
+struct HighestThreat;
+
+impl TargetingStrategy for HighestThreat {
+    fn select_target(&self, attacker: &BattleOrder, combatants: &[BattleOrder], _ruleset: &dyn Ruleset) -> Option<usize> {
+        enemies(attacker, combatants)
+            .max_by_key(|(i, target)| (target.character.expected_damage(), std::cmp::Reverse(*i)))
+            .map(|(i, _)| i)
+    }
+}
+
+struct LowestArmourClass;
+
+impl TargetingStrategy for LowestArmourClass {
+    fn select_target(&self, attacker: &BattleOrder, combatants: &[BattleOrder], _ruleset: &dyn Ruleset) -> Option<usize> {
+        enemies(attacker, combatants)
+            .min_by_key(|(_, target)| target.character.armour_class)
+            .map(|(i, _)| i)
+    }
+}
+
+struct FocusFire;
+
+impl TargetingStrategy for FocusFire {
+    fn select_target(&self, attacker: &BattleOrder, combatants: &[BattleOrder], _ruleset: &dyn Ruleset) -> Option<usize> {
+        enemies(attacker, combatants)
+            .max_by_key(|(i, target)| (ally_pressure(attacker, combatants, *i), std::cmp::Reverse(target.character.hit_points())))
+            .map(|(i, _)| i)
+    }
+}
+
+struct SpreadDamage;
+
+impl TargetingStrategy for SpreadDamage {
+    fn select_target(&self, attacker: &BattleOrder, combatants: &[BattleOrder], _ruleset: &dyn Ruleset) -> Option<usize> {
+        enemies(attacker, combatants)
+            .min_by_key(|(i, _)| ally_pressure(attacker, combatants, *i))
+            .map(|(i, _)| i)
+    }
+}
+
+struct FinishOffDowned;
+
+impl TargetingStrategy for FinishOffDowned {
+    /// Under 5e a downed creature only dies to more than 10 damage in one
+    /// hit, so a weak attacker leaves it be and fights whoever is standing.
+    fn select_target(&self, attacker: &BattleOrder, combatants: &[BattleOrder], ruleset: &dyn Ruleset) -> Option<usize> {
+        let finished_by_a_hit = ruleset.take_damage(HealthState::Ko, attacker.character.damage as u16) == HealthState::Dead;
+        combatants.iter()
+            .position(|target| finished_by_a_hit && target.team != attacker.team && target.character.hs2 == HealthState::Ko
+                && attacker.within_reach_this_turn(target))
+            .or_else(|| First.select_target(attacker, combatants, ruleset))
+    }
+}